@@ -0,0 +1,203 @@
+use crate::hash3::hash::map::HashTable;
+use crate::hash3::hash::{KeyHasher, NoHash, ProbeStrategy, Triangular};
+use std::fmt::{self, Display};
+
+/// Measurements of how a hasher spreads one key set over a `HashTable`, produced by
+/// `analyze`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HashReport {
+    /// The number of distinct keys analyzed.
+    pub keys: usize,
+    pub capacity: usize,
+    pub shift: usize,
+    pub mask: usize,
+    /// `occupancy[i]` is the number of home buckets that exactly `i` keys hash to.
+    pub occupancy: Vec<usize>,
+    /// The mean length of a non-empty chain if the hasher were uniformly random.
+    pub expected_chain_length: f64,
+    /// The mean length of the non-empty chains in the built table.
+    pub mean_chain_length: f64,
+    pub max_chain_length: usize,
+    /// The mean number of buckets a successful `get` visits if the hasher were uniformly
+    /// random.
+    pub expected_probes: f64,
+    /// The mean number of buckets a successful `get` visits in the built table.
+    pub mean_probes: f64,
+    /// How many inserts found their home bucket holding a member of another chain and
+    /// had to move it out.
+    pub displaced: usize,
+    /// Whether a chain became long enough that a table with automatic rehash would have
+    /// rebuilt itself with a fresh seed. The table analyzed has automatic rehash turned
+    /// off, so every measurement describes the given hasher.
+    pub long_chains: bool,
+    /// The mean distance in buckets, around the table, from a chain member to the next.
+    /// Short links mean a chain walk touches few cache lines.
+    pub mean_link_span: f64,
+    /// Pearson's chi-squared statistic of the home bucket counts against a uniform
+    /// distribution, with `capacity - 1` degrees of freedom.
+    pub chi_squared: f64,
+    /// The chi-squared statistic normalized to a standard score. Values near zero mean
+    /// the keys are spread like random keys; large positive values mean clustering.
+    pub chi_squared_z: f64,
+}
+
+/// Inserts `keys` into a table created with `initial_capacity` and `hasher` and measures
+/// the result. The table grows as it would in use, so the report describes the final
+/// `shift` and `mask`. Duplicate keys are counted once.
+pub fn analyze<H>(keys: &[usize], hasher: &H, initial_capacity: usize) -> HashReport
+where
+    H: KeyHasher,
+{
+    analyze_with_probe::<H, Triangular>(keys, hasher, initial_capacity)
+}
+
+/// Like `analyze`, for a table that searches for free buckets with the probe strategy `P`.
+pub fn analyze_with_probe<H, P>(keys: &[usize], hasher: &H, initial_capacity: usize) -> HashReport
+where
+    H: KeyHasher,
+    P: ProbeStrategy,
+{
+    let mut table: HashTable<u8, usize, H, NoHash, P> =
+        HashTable::with_capacity_and_hasher(initial_capacity, hasher.clone());
+    table.set_auto_rehash(false);
+    for key in keys {
+        table.put(*key, 0);
+    }
+
+    let shift: usize = table.shift();
+    let capacity: usize = table.capacity();
+    let count: usize = table.len();
+    let mut homes: Vec<usize> = vec![0; capacity];
+    for (key, _) in table.iter() {
+        homes[hasher.hash(key) >> shift] += 1;
+    }
+
+    let mut occupancy: Vec<usize> = Vec::new();
+    let mut chains: usize = 0;
+    let mut probes: usize = 0;
+    let mut chi_squared: f64 = 0.0;
+    let expected_per_bucket: f64 = count as f64 / capacity as f64;
+    for keys_at_home in homes.iter().copied() {
+        if occupancy.len() <= keys_at_home {
+            occupancy.resize(keys_at_home + 1, 0);
+        }
+        occupancy[keys_at_home] += 1;
+        if keys_at_home > 0 {
+            chains += 1;
+            // The i-th key of a chain is found after visiting i buckets.
+            probes += keys_at_home * (keys_at_home + 1) / 2;
+        }
+        let diff: f64 = keys_at_home as f64 - expected_per_bucket;
+        chi_squared += diff * diff;
+    }
+    if expected_per_bucket > 0.0 {
+        chi_squared /= expected_per_bucket;
+    }
+    let freedom: f64 = (capacity - 1) as f64;
+
+    let mut links: usize = 0;
+    let mut span: usize = 0;
+    for (index, bucket) in table.occupied() {
+        let next: usize = table.next_index(bucket);
+        if next != index {
+            let distance: usize = next.abs_diff(index);
+            span += distance.min(capacity - distance);
+            links += 1;
+        }
+    }
+
+    // Each chain holds exactly the keys of its home bucket, so for uniformly random keys
+    // the chain lengths are Poisson distributed with mean equal to the load factor.
+    let load: f64 = expected_per_bucket;
+    let expected_chain_length: f64 = if load > 0.0 {
+        load / (1.0 - (-load).exp())
+    } else {
+        0.0
+    };
+
+    HashReport {
+        keys: count,
+        capacity,
+        shift,
+        mask: capacity - 1,
+        max_chain_length: occupancy.len().saturating_sub(1),
+        occupancy,
+        expected_chain_length,
+        mean_chain_length: if chains > 0 {
+            count as f64 / chains as f64
+        } else {
+            0.0
+        },
+        expected_probes: if count > 0 { 1.0 + load / 2.0 } else { 0.0 },
+        mean_probes: if count > 0 {
+            probes as f64 / count as f64
+        } else {
+            0.0
+        },
+        displaced: table.displaced(),
+        long_chains: table.chains_too_long(),
+        mean_link_span: if links > 0 {
+            span as f64 / links as f64
+        } else {
+            0.0
+        },
+        chi_squared,
+        chi_squared_z: (chi_squared - freedom) / (2.0 * freedom).sqrt(),
+    }
+}
+
+impl Display for HashReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "keys {}, capacity {}, shift {}, mask {:#x}, load {:.4}",
+            self.keys,
+            self.capacity,
+            self.shift,
+            self.mask,
+            self.keys as f64 / self.capacity as f64
+        )?;
+        writeln!(f, "home bucket occupancy (keys: buckets)")?;
+        for (keys, buckets) in self.occupancy.iter().enumerate() {
+            if *buckets > 0 {
+                writeln!(f, "  {:>4}: {}", keys, buckets)?;
+            }
+        }
+        writeln!(
+            f,
+            "chain length   expected {:.4}, actual {:.4}, max {}",
+            self.expected_chain_length, self.mean_chain_length, self.max_chain_length
+        )?;
+        writeln!(
+            f,
+            "probes per hit expected {:.4}, actual {:.4}",
+            self.expected_probes, self.mean_probes
+        )?;
+        writeln!(f, "displaced origin buckets {}", self.displaced)?;
+        writeln!(f, "chains long enough to rehash {}", self.long_chains)?;
+        writeln!(f, "mean chain link span {:.2} buckets", self.mean_link_span)?;
+        write!(
+            f,
+            "chi-squared {:.1} on {} degrees of freedom, z {:.2}",
+            self.chi_squared,
+            self.capacity - 1,
+            self.chi_squared_z
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::analyze;
+    use crate::hash3::hash::{one_home_keys, Fibonacci};
+
+    #[test]
+    fn reports_the_given_hasher_despite_long_chains() {
+        let keys: Vec<usize> = one_home_keys(5000);
+        let report = analyze(&keys, &Fibonacci::default(), 16);
+        assert_eq!(report.keys, 5000);
+        assert!(report.long_chains);
+        assert_eq!(report.max_chain_length, 5000);
+        assert!(report.chi_squared_z > 1000.0, "z {}", report.chi_squared_z);
+    }
+}
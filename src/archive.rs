@@ -0,0 +1,449 @@
+use crate::hash3::hash::map::HashTable;
+use crate::hash3::hash::HashSeed;
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+use std::mem;
+
+/// Marker for value types that may be reinterpreted directly from the bytes of an archive.
+///
+/// # Safety
+///
+/// Every bit pattern of the implementing type must be a valid value and the type must not
+/// contain padding, pointers or references.
+pub unsafe trait Plain: Copy + 'static {}
+
+unsafe impl Plain for u8 {}
+unsafe impl Plain for u16 {}
+unsafe impl Plain for u32 {}
+unsafe impl Plain for u64 {}
+unsafe impl Plain for u128 {}
+unsafe impl Plain for usize {}
+unsafe impl Plain for i8 {}
+unsafe impl Plain for i16 {}
+unsafe impl Plain for i32 {}
+unsafe impl Plain for i64 {}
+unsafe impl Plain for i128 {}
+unsafe impl Plain for isize {}
+unsafe impl Plain for f32 {}
+unsafe impl Plain for f64 {}
+unsafe impl<T: Plain, const N: usize> Plain for [T; N] {}
+
+/// "RBARCHV1" read as a native-endian word, so archives written on a machine with the
+/// other byte order are rejected by the magic check.
+const MAGIC: u64 = u64::from_ne_bytes(*b"RBARCHV1");
+
+/// Marks an empty bucket in place of the null `next` pointer used by `HashTable`.
+pub(crate) const EMPTY: usize = usize::MAX;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Header {
+    magic: u64,
+    bucket_size: u64,
+    value_size: u64,
+    capacity: u64,
+    count: u64,
+    shift: u64,
+    seed: [u64; 3],
+}
+
+/// A bucket exactly as in `HashTable`, except that `next` is the index of the successor
+/// in the chain rather than a pointer, so the array is position independent.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ArchivedBucket<T> {
+    pub(crate) next: usize,
+    pub(crate) key: usize,
+    pub(crate) value: T,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveError {
+    /// The buffer is shorter than the header or the bucket array it describes.
+    Truncated,
+    /// The buffer does not start on the alignment required by the buckets.
+    Misaligned,
+    /// The buffer is not an archive, or it was written with a different byte order.
+    BadMagic,
+    /// The archive was written for a different value type or word size.
+    LayoutMismatch,
+    /// The header or a chain link is inconsistent with the bucket array.
+    Corrupt,
+}
+
+impl Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg: &str = match self {
+            ArchiveError::Truncated => "buffer is too short for the archived table",
+            ArchiveError::Misaligned => "buffer is not aligned for the archived buckets",
+            ArchiveError::BadMagic => "buffer does not contain an archived table",
+            ArchiveError::LayoutMismatch => "archived bucket layout does not match the value type",
+            ArchiveError::Corrupt => "archived table is corrupt",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl Error for ArchiveError {}
+
+/// A read-only view of a `HashTable` frozen into a byte buffer with `ArchivedTable::freeze`.
+///
+/// The buffer holds a small header followed by the bucket array laid out exactly as it was
+/// probed, so `get` walks the same chains as `HashTable::get` without deserializing or
+/// allocating. The buffer must start on an address aligned for the buckets; data embedded
+/// with `include_bytes!` should be copied into, or wrapped by, a suitably aligned type.
+#[derive(Clone, Copy)]
+pub struct ArchivedTable<'a, T>
+where
+    T: Plain,
+{
+    count: usize,
+    shift: usize,
+    seed: HashSeed,
+    buckets: &'a [ArchivedBucket<T>],
+}
+
+impl<'a, T> ArchivedTable<'a, T>
+where
+    T: Plain,
+{
+    const BUCKET_SIZE: usize = mem::size_of::<ArchivedBucket<T>>();
+    const BODY_OFFSET: usize = {
+        let align: usize = mem::align_of::<ArchivedBucket<T>>();
+        (mem::size_of::<Header>() + align - 1) & !(align - 1)
+    };
+
+    /// Serializes the table into the archive format read by `from_bytes`. An incremental
+    /// resize in progress must be finished first, with `HashTable::finish_resize`.
+    pub fn freeze(table: &HashTable<T>) -> Vec<u8>
+    where
+        T: Default + Debug,
+    {
+        assert!(!table.is_resizing(), "Resize in progress");
+        let capacity: usize = table.capacity();
+        let mut bytes: Vec<u8> =
+            Vec::with_capacity(Self::BODY_OFFSET + capacity * Self::BUCKET_SIZE);
+        let seed: [usize; 3] = table.seed().to_words();
+        let header: [u64; 9] = [
+            MAGIC,
+            Self::BUCKET_SIZE as u64,
+            mem::size_of::<T>() as u64,
+            capacity as u64,
+            table.len() as u64,
+            table.shift() as u64,
+            seed[0] as u64,
+            seed[1] as u64,
+            seed[2] as u64,
+        ];
+        for word in header {
+            bytes.extend_from_slice(&word.to_ne_bytes());
+        }
+        bytes.resize(Self::BODY_OFFSET, 0);
+
+        // Empty buckets are written as EMPTY followed by zeros; their keys and values in
+        // the table may never have been initialized.
+        let value_offset: usize = mem::offset_of!(ArchivedBucket<T>, value);
+        let mut occupied = table.occupied().peekable();
+        for index in 0..capacity {
+            let start: usize = bytes.len();
+            match occupied.next_if(|(at, _)| *at == index) {
+                Some((_, bucket)) => {
+                    bytes.extend_from_slice(&table.next_index(bucket).to_ne_bytes());
+                    bytes.extend_from_slice(&bucket.key.to_ne_bytes());
+                    bytes.resize(start + value_offset, 0);
+                    let value: &[u8] = unsafe {
+                        std::slice::from_raw_parts(
+                            &bucket.value as *const T as *const u8,
+                            mem::size_of::<T>(),
+                        )
+                    };
+                    bytes.extend_from_slice(value);
+                }
+                None => bytes.extend_from_slice(&EMPTY.to_ne_bytes()),
+            }
+            bytes.resize(start + Self::BUCKET_SIZE, 0);
+        }
+        bytes
+    }
+
+    /// Validates `bytes` as an archive of `T` values and returns a view over it.
+    ///
+    /// The header, the buffer length and alignment, and every chain link are checked up
+    /// front, so lookups on the returned table never index outside the buffer.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, ArchiveError> {
+        if bytes.len() < mem::size_of::<Header>() {
+            return Err(ArchiveError::Truncated);
+        }
+        let align: usize = mem::align_of::<ArchivedBucket<T>>().max(mem::align_of::<Header>());
+        if !(bytes.as_ptr() as usize).is_multiple_of(align) {
+            return Err(ArchiveError::Misaligned);
+        }
+        let header: Header = unsafe { *(bytes.as_ptr() as *const Header) };
+        if header.magic != MAGIC {
+            return Err(ArchiveError::BadMagic);
+        }
+        if header.bucket_size != Self::BUCKET_SIZE as u64
+            || header.value_size != mem::size_of::<T>() as u64
+        {
+            return Err(ArchiveError::LayoutMismatch);
+        }
+
+        let capacity: usize =
+            usize::try_from(header.capacity).map_err(|_| ArchiveError::Truncated)?;
+        if !capacity.is_power_of_two()
+            || header.shift != capacity.leading_zeros() as u64 + 1
+            || header.count > header.capacity
+        {
+            return Err(ArchiveError::Corrupt);
+        }
+        let seed: HashSeed = HashSeed::from_words(header.seed.map(|word| word as usize))
+            .ok_or(ArchiveError::Corrupt)?;
+        let end: usize = capacity
+            .checked_mul(Self::BUCKET_SIZE)
+            .and_then(|size| size.checked_add(Self::BODY_OFFSET))
+            .ok_or(ArchiveError::Truncated)?;
+        if bytes.len() < end {
+            return Err(ArchiveError::Truncated);
+        }
+
+        let buckets: &'a [ArchivedBucket<T>] = unsafe {
+            std::slice::from_raw_parts(
+                bytes.as_ptr().add(Self::BODY_OFFSET) as *const ArchivedBucket<T>,
+                capacity,
+            )
+        };
+        let mut occupied: usize = 0;
+        for bucket in buckets {
+            if bucket.next != EMPTY {
+                if bucket.next >= capacity {
+                    return Err(ArchiveError::Corrupt);
+                }
+                occupied += 1;
+            }
+        }
+        if occupied as u64 != header.count {
+            return Err(ArchiveError::Corrupt);
+        }
+
+        Ok(ArchivedTable {
+            count: occupied,
+            shift: header.shift as usize,
+            seed,
+            buckets,
+        })
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.buckets.len()
+    }
+
+    #[inline]
+    pub fn load_factor(&self) -> f64 {
+        if self.buckets.is_empty() {
+            0.0
+        } else {
+            self.count as f64 / self.buckets.len() as f64
+        }
+    }
+
+    /// Looks up `key` by walking its chain in the archived bucket array. A chain is never
+    /// followed for more than `capacity` hops, so a buffer with a malformed cycle can not
+    /// make a lookup spin forever.
+    #[inline]
+    pub fn get(&self, key: usize) -> Option<&'a T> {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        let origin: usize = self.seed.hash(key, self.shift);
+        let mut index: usize = origin;
+        let buckets: &'a [ArchivedBucket<T>] = self.buckets;
+        if buckets[origin].next == EMPTY {
+            return None;
+        }
+        for _ in 0..buckets.len() {
+            let bucket: &'a ArchivedBucket<T> = &buckets[index];
+            if bucket.key == key {
+                return Some(&bucket.value);
+            }
+            index = bucket.next;
+            if index == origin || index == EMPTY {
+                return None;
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArchiveError, ArchivedTable, EMPTY};
+    use crate::hash3::hash::map::HashTable;
+    use std::mem;
+
+    /// Offset of the bucket array: the header is nine words.
+    const BODY: usize = 9 * 8;
+
+    /// Copies an archive into word-aligned storage, as `from_bytes` requires.
+    fn aligned(bytes: &[u8]) -> Vec<u64> {
+        let mut words: Vec<u64> = vec![0; bytes.len().div_ceil(8)];
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                bytes.as_ptr(),
+                words.as_mut_ptr() as *mut u8,
+                bytes.len(),
+            )
+        };
+        words
+    }
+
+    fn as_bytes(words: &[u64]) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(words.as_ptr() as *const u8, words.len() * 8) }
+    }
+
+    fn frozen() -> Vec<u64> {
+        let mut table: HashTable<u64> = HashTable::with_capacity(16);
+        for key in 0..100usize {
+            table.put(key * 7919, key as u64);
+        }
+        aligned(&ArchivedTable::freeze(&table))
+    }
+
+    /// Overwrites the word at byte offset `offset`.
+    fn set_word(words: &mut [u64], offset: usize, word: u64) {
+        words[offset / 8] = word;
+    }
+
+    #[test]
+    fn round_trip() {
+        let words: Vec<u64> = frozen();
+        let archived: ArchivedTable<u64> = ArchivedTable::from_bytes(as_bytes(&words)).unwrap();
+        assert_eq!(archived.len(), 100);
+        for key in 0..100usize {
+            assert_eq!(archived.get(key * 7919), Some(&(key as u64)));
+        }
+        assert_eq!(archived.get(1), None);
+    }
+
+    #[test]
+    fn empty_buckets_are_frozen_as_zeros() {
+        let words: Vec<u64> = frozen();
+        let bucket_words: usize = mem::size_of::<super::ArchivedBucket<u64>>() / 8;
+        let mut empty: usize = 0;
+        for bucket in words[BODY / 8..].chunks(bucket_words) {
+            if bucket[0] == EMPTY as u64 {
+                assert!(bucket[1..].iter().all(|word| *word == 0));
+                empty += 1;
+            }
+        }
+        assert!(empty > 0);
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        let words: Vec<u64> = frozen();
+        let bytes: &[u8] = as_bytes(&words);
+        for len in [0, BODY - 1, bytes.len() - 1] {
+            assert_eq!(
+                ArchivedTable::<u64>::from_bytes(&bytes[..len]).err(),
+                Some(ArchiveError::Truncated)
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_misaligned_input() {
+        let words: Vec<u64> = frozen();
+        let mut shifted: Vec<u64> = vec![0; words.len() + 1];
+        let bytes: &[u8] = as_bytes(&words);
+        let start: *mut u8 = unsafe { (shifted.as_mut_ptr() as *mut u8).add(1) };
+        unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), start, bytes.len()) };
+        let misaligned: &[u8] = &as_bytes(&shifted)[1..bytes.len() + 1];
+        assert_eq!(
+            ArchivedTable::<u64>::from_bytes(misaligned).err(),
+            Some(ArchiveError::Misaligned)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut words: Vec<u64> = frozen();
+        set_word(&mut words, 0, u64::from_ne_bytes(*b"NOTATABL"));
+        assert_eq!(
+            ArchivedTable::<u64>::from_bytes(as_bytes(&words)).err(),
+            Some(ArchiveError::BadMagic)
+        );
+    }
+
+    #[test]
+    fn rejects_other_value_types() {
+        let words: Vec<u64> = frozen();
+        assert_eq!(mem::size_of::<super::ArchivedBucket<u32>>(), 24);
+        assert_eq!(
+            ArchivedTable::<u32>::from_bytes(as_bytes(&words)).err(),
+            Some(ArchiveError::LayoutMismatch)
+        );
+    }
+
+    #[test]
+    fn rejects_corrupt_headers() {
+        // Capacity not a power of two, shift not matching it, and count above capacity.
+        for (offset, word) in [(24, 100), (40, 3), (32, u64::MAX)] {
+            let mut words: Vec<u64> = frozen();
+            set_word(&mut words, offset, word);
+            assert_eq!(
+                ArchivedTable::<u64>::from_bytes(as_bytes(&words)).err(),
+                Some(ArchiveError::Corrupt)
+            );
+        }
+        // A count that disagrees with the occupied buckets.
+        let mut words: Vec<u64> = frozen();
+        set_word(&mut words, 32, 99);
+        assert_eq!(
+            ArchivedTable::<u64>::from_bytes(as_bytes(&words)).err(),
+            Some(ArchiveError::Corrupt)
+        );
+    }
+
+    #[test]
+    fn rejects_links_outside_the_array() {
+        let mut words: Vec<u64> = frozen();
+        let capacity: u64 = words[3];
+        let bucket: usize = (0..capacity as usize)
+            .map(|index| BODY + index * 24)
+            .find(|offset| words[offset / 8] != EMPTY as u64)
+            .unwrap();
+        set_word(&mut words, bucket, capacity);
+        assert_eq!(
+            ArchivedTable::<u64>::from_bytes(as_bytes(&words)).err(),
+            Some(ArchiveError::Corrupt)
+        );
+    }
+
+    #[test]
+    fn lookups_stop_on_malformed_cycles() {
+        let mut words: Vec<u64> = frozen();
+        let capacity: usize = words[3] as usize;
+        // Point every occupied bucket at bucket 1, so chains loop without their origin.
+        for index in 0..capacity {
+            let offset: usize = BODY + index * 24;
+            if words[offset / 8] != EMPTY as u64 {
+                set_word(&mut words, offset, 1);
+            }
+        }
+        let archived: ArchivedTable<u64> = ArchivedTable::from_bytes(as_bytes(&words)).unwrap();
+        for key in 0..1000usize {
+            archived.get(key);
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use crate::hash3::hash::{Fibonacci, KeyHasher, USIZE_BITS};
+use std::fmt::Debug;
+
+/// Marks an empty bucket.
+const EMPTY: usize = usize::MAX;
+
+/// Marks the last bucket of a chain.
+const END: usize = usize::MAX - 1;
+
+/// The cellar fraction Vitter found to minimize successful search cost over most load
+/// factors: about 14% of the buckets, an address factor of 0.86.
+pub const DEFAULT_CELLAR_FRACTION: f64 = 0.14;
+
+#[derive(Clone, Copy, Debug, Default)]
+struct CellarBucket<T>
+where
+    T: Default + Copy + Clone + Debug,
+{
+    next: usize,
+    key: usize,
+    value: T,
+}
+
+/// Coalesced hashing with a cellar.
+///
+/// The table hashes keys into an address region of a power of two buckets, followed by a
+/// cellar that no key hashes to. A key that collides is appended to the end of the chain
+/// through its home bucket, in a free bucket taken from the highest index still unused,
+/// so collisions fill the cellar before they spill into the address region. Until the
+/// cellar is exhausted chains never coalesce, and after that they coalesce much less
+/// than without a cellar, which keeps probe counts low at load factors close to one.
+///
+/// Unlike `HashTable`, a bucket is never moved once filled, so chains can hold keys of
+/// several home buckets.
+#[derive(Clone, Debug)]
+pub struct CellarHashTable<T, H = Fibonacci>
+where
+    T: Default + Copy + Clone + Debug,
+    H: KeyHasher,
+{
+    count: usize,
+    shift: usize,
+    address: usize,
+    /// Every bucket at or above this index is occupied.
+    free: usize,
+    cellar_fraction: f64,
+    hasher: H,
+    buckets: Vec<CellarBucket<T>>,
+}
+
+impl<T> CellarHashTable<T, Fibonacci>
+where
+    T: Default + Copy + Clone + Debug,
+{
+    pub fn with_capacity(initial_capacity: usize) -> Self {
+        Self::with_cellar(initial_capacity, DEFAULT_CELLAR_FRACTION)
+    }
+
+    /// Creates a table that reserves `cellar_fraction` of its buckets as the cellar. A
+    /// fraction of zero gives standard coalesced hashing with no cellar.
+    pub fn with_cellar(initial_capacity: usize, cellar_fraction: f64) -> Self {
+        Self::with_cellar_and_hasher(initial_capacity, cellar_fraction, Fibonacci::default())
+    }
+}
+
+impl<T, H> CellarHashTable<T, H>
+where
+    T: Default + Copy + Clone + Debug,
+    H: KeyHasher,
+{
+    pub const MIN_BITS: usize = 1;
+
+    pub fn with_cellar_and_hasher(
+        initial_capacity: usize,
+        cellar_fraction: f64,
+        hasher: H,
+    ) -> Self {
+        assert!(
+            (0.0..1.0).contains(&cellar_fraction),
+            "Cellar fraction must be in [0, 1)"
+        );
+        let bits: usize =
+            (USIZE_BITS - initial_capacity.leading_zeros() as usize).max(Self::MIN_BITS);
+        Self::allocate(bits, cellar_fraction, hasher)
+    }
+
+    fn allocate(bits: usize, cellar_fraction: f64, hasher: H) -> Self {
+        assert!(bits < USIZE_BITS, "Allocation too large");
+        let address: usize = 1 << bits;
+        let cellar: usize =
+            (address as f64 * cellar_fraction / (1.0 - cellar_fraction)).ceil() as usize;
+        let capacity: usize = address.checked_add(cellar).expect("Allocation too large");
+        CellarHashTable {
+            count: 0,
+            shift: USIZE_BITS - bits,
+            address,
+            free: capacity,
+            cellar_fraction,
+            hasher,
+            buckets: vec![
+                CellarBucket {
+                    next: EMPTY,
+                    ..CellarBucket::default()
+                };
+                capacity
+            ],
+        }
+    }
+
+    #[inline]
+    fn hash(&self, key: usize) -> usize {
+        self.hasher.hash(key) >> self.shift
+    }
+
+    /// Returns true if the load factor greater than or equal to 0.9375.
+    #[inline]
+    fn should_grow(&self) -> bool {
+        self.count >= (self.buckets.len() - (self.buckets.len() >> 4))
+    }
+
+    /// Returns the load factor over all buckets, cellar included.
+    #[inline]
+    pub fn load_factor(&self) -> f64 {
+        self.count as f64 / self.buckets.len() as f64
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Returns the number of buckets, cellar included.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// Returns the number of bytes allocated for buckets.
+    #[inline]
+    pub fn allocated_bytes(&self) -> usize {
+        self.buckets.len() * std::mem::size_of::<CellarBucket<T>>()
+    }
+
+    #[inline]
+    pub fn cellar_capacity(&self) -> usize {
+        self.buckets.len() - self.address
+    }
+
+    #[inline]
+    pub fn get(&self, key: usize) -> Option<&T> {
+        let mut index: usize = self.hash(key);
+        if self.buckets[index].next == EMPTY {
+            return None;
+        }
+        loop {
+            let bucket: &CellarBucket<T> = &self.buckets[index];
+            if bucket.key == key {
+                return Some(&bucket.value);
+            }
+            if bucket.next == END {
+                return None;
+            }
+            index = bucket.next;
+        }
+    }
+
+    pub fn put(&mut self, key: usize, value: T) -> Option<T> {
+        if self.should_grow() {
+            self.grow();
+        }
+        let home: usize = self.hash(key);
+        if self.buckets[home].next == EMPTY {
+            self.buckets[home] = CellarBucket {
+                next: END,
+                key,
+                value,
+            };
+            self.count += 1;
+            return None;
+        }
+
+        let mut tail: usize = home;
+        loop {
+            let bucket: &mut CellarBucket<T> = &mut self.buckets[tail];
+            if bucket.key == key {
+                return Some(std::mem::replace(&mut bucket.value, value));
+            }
+            if bucket.next == END {
+                break;
+            }
+            tail = bucket.next;
+        }
+
+        while self.free > 0 {
+            self.free -= 1;
+            if self.buckets[self.free].next == EMPTY {
+                self.buckets[self.free] = CellarBucket {
+                    next: END,
+                    key,
+                    value,
+                };
+                self.buckets[tail].next = self.free;
+                self.count += 1;
+                return None;
+            }
+        }
+        // The free index only passes buckets that are occupied, so it can only run out on a
+        // full table, which the growth threshold already prevents.
+        self.grow();
+        self.put(key, value)
+    }
+
+    fn grow(&mut self) {
+        let bits: usize = USIZE_BITS - self.shift + 1;
+        let hasher: H = self.hasher.clone();
+        let old: Self = std::mem::replace(self, Self::allocate(bits, self.cellar_fraction, hasher));
+        for bucket in old.buckets.iter() {
+            if bucket.next != EMPTY {
+                self.put(bucket.key, bucket.value);
+            }
+        }
+    }
+
+    /// Returns the mean number of buckets a successful `get` visits, averaged over the keys
+    /// in the table.
+    pub fn mean_probes(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        let mut probes: usize = 0;
+        for bucket in self.buckets.iter() {
+            if bucket.next == EMPTY {
+                continue;
+            }
+            let mut index: usize = self.hash(bucket.key);
+            probes += 1;
+            while self.buckets[index].key != bucket.key {
+                index = self.buckets[index].next;
+                probes += 1;
+            }
+        }
+        probes as f64 / self.count as f64
+    }
+
+    pub fn print(&self) {
+        println!(
+            "count {}, shift {}, address {}, cellar {}, load {}",
+            self.count,
+            self.shift,
+            self.address,
+            self.cellar_capacity(),
+            self.load_factor()
+        );
+    }
+}
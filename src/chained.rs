@@ -0,0 +1,360 @@
+use crate::hash3::hash::{Fibonacci, HashSeed, Key, KeyHasher, TableKey, USIZE_BITS};
+use std::fmt::{self, Debug};
+use std::mem;
+
+/// The load factor at which a table grows unless told otherwise.
+pub const DEFAULT_MAX_LOAD: f64 = 1.0;
+
+struct ChainNode<K, T>
+where
+    K: TableKey,
+    T: Default + Copy + Clone + Debug,
+{
+    key: K,
+    value: T,
+    next: Option<Box<ChainNode<K, T>>>,
+}
+
+/// Separate chaining with a singly linked list of heap nodes per home bucket, for
+/// comparison with the in-array chains of `HashTable`.
+///
+/// Because the chains live outside the bucket array, the load factor can go above one:
+/// `with_max_load` sets how many keys per bucket the table holds before it doubles. Growing
+/// only relinks the nodes into the new buckets, so no key or value is copied. Each key
+/// costs a heap allocation, and `allocated_bytes` counts the nodes but not the allocator's
+/// own overhead.
+///
+/// A weak hasher can make a single chain very long, so `Clone`, `Debug` and `Drop` walk
+/// chains with loops rather than recursing once per node.
+pub struct ChainedTable<T, K = usize, H = Fibonacci>
+where
+    T: Default + Copy + Clone + Debug,
+    K: TableKey,
+    H: KeyHasher<K>,
+{
+    count: usize,
+    shift: usize,
+    max_load: f64,
+    hasher: H,
+    heads: Vec<Option<Box<ChainNode<K, T>>>>,
+}
+
+impl<T, K> ChainedTable<T, K, Fibonacci>
+where
+    T: Default + Copy + Clone + Debug,
+    K: Key,
+{
+    pub fn with_capacity(initial_capacity: usize) -> Self {
+        Self::with_max_load(initial_capacity, DEFAULT_MAX_LOAD)
+    }
+
+    /// Creates a table that grows once it holds `max_load` keys per bucket.
+    pub fn with_max_load(initial_capacity: usize, max_load: f64) -> Self {
+        Self::with_max_load_and_hasher(initial_capacity, max_load, Fibonacci::default())
+    }
+
+    pub fn with_capacity_and_seed(initial_capacity: usize, seed: HashSeed) -> Self {
+        Self::with_max_load_and_hasher(
+            initial_capacity,
+            DEFAULT_MAX_LOAD,
+            Fibonacci::with_seed(seed),
+        )
+    }
+}
+
+impl<T, K, H> ChainedTable<T, K, H>
+where
+    T: Default + Copy + Clone + Debug,
+    K: TableKey,
+    H: KeyHasher<K>,
+{
+    pub const NODE_SIZE: usize = mem::size_of::<ChainNode<K, T>>();
+    pub const MIN_BITS: usize = 1;
+
+    /// Creates a table with enough buckets for `initial_capacity` keys at `max_load` keys
+    /// per bucket.
+    pub fn with_max_load_and_hasher(initial_capacity: usize, max_load: f64, hasher: H) -> Self {
+        assert!(max_load > 0.0, "Maximum load must be positive");
+        let buckets: usize = (initial_capacity as f64 / max_load).ceil() as usize;
+        let bits: usize = (USIZE_BITS - buckets.leading_zeros() as usize).max(Self::MIN_BITS);
+        Self::allocate(bits, max_load, hasher)
+    }
+
+    fn allocate(bits: usize, max_load: f64, hasher: H) -> Self {
+        assert!(bits < USIZE_BITS, "Allocation too large");
+        ChainedTable {
+            count: 0,
+            shift: USIZE_BITS - bits,
+            max_load,
+            hasher,
+            heads: (0..1usize << bits).map(|_| None).collect(),
+        }
+    }
+
+    #[inline]
+    fn hash(&self, key: K) -> usize {
+        self.hasher.hash(key) >> self.shift
+    }
+
+    #[inline]
+    fn should_grow(&self) -> bool {
+        self.count as f64 >= self.heads.len() as f64 * self.max_load
+    }
+
+    /// Returns the mean number of keys per bucket, which may be above one.
+    #[inline]
+    pub fn load_factor(&self) -> f64 {
+        self.count as f64 / self.heads.len() as f64
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Returns the number of buckets.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.heads.len()
+    }
+
+    /// Returns the number of bytes allocated for the bucket array and the nodes.
+    #[inline]
+    pub fn allocated_bytes(&self) -> usize {
+        self.heads.len() * mem::size_of::<Option<Box<ChainNode<K, T>>>>()
+            + self.count * Self::NODE_SIZE
+    }
+
+    #[inline]
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    #[inline]
+    pub fn get(&self, key: K) -> Option<&T> {
+        let mut link: &Option<Box<ChainNode<K, T>>> = &self.heads[self.hash(key)];
+        while let Some(node) = link {
+            if node.key == key {
+                return Some(&node.value);
+            }
+            link = &node.next;
+        }
+        None
+    }
+
+    pub fn put(&mut self, key: K, value: T) -> Option<T> {
+        let home: usize = self.hash(key);
+        let mut link: &mut Option<Box<ChainNode<K, T>>> = &mut self.heads[home];
+        while let Some(node) = link {
+            if node.key == key {
+                return Some(mem::replace(&mut node.value, value));
+            }
+            link = &mut node.next;
+        }
+        if self.should_grow() {
+            self.grow();
+        }
+        let home: usize = self.hash(key);
+        let next: Option<Box<ChainNode<K, T>>> = self.heads[home].take();
+        self.heads[home] = Some(Box::new(ChainNode { key, value, next }));
+        self.count += 1;
+        None
+    }
+
+    pub fn remove(&mut self, key: K) -> Option<T> {
+        let home: usize = self.hash(key);
+        let mut link: &mut Option<Box<ChainNode<K, T>>> = &mut self.heads[home];
+        while link.as_ref().is_some_and(|node| node.key != key) {
+            link = &mut link.as_mut().expect("Checked above").next;
+        }
+        let node: Box<ChainNode<K, T>> = link.take()?;
+        *link = node.next;
+        self.count -= 1;
+        Some(node.value)
+    }
+
+    /// Doubles the number of buckets and moves every node to the front of its new chain.
+    fn grow(&mut self) {
+        let bits: usize = USIZE_BITS - self.shift + 1;
+        let mut old: Self = mem::replace(
+            self,
+            Self::allocate(bits, self.max_load, self.hasher.clone()),
+        );
+        for mut link in mem::take(&mut old.heads) {
+            while let Some(mut node) = link {
+                link = node.next.take();
+                let home: usize = self.hash(node.key);
+                node.next = self.heads[home].take();
+                self.heads[home] = Some(node);
+            }
+        }
+        self.count = old.count;
+    }
+
+    /// Returns the length of the longest chain.
+    pub fn longest_chain(&self) -> usize {
+        self.heads
+            .iter()
+            .map(|head| {
+                let mut len: usize = 0;
+                let mut link: &Option<Box<ChainNode<K, T>>> = head;
+                while let Some(node) = link {
+                    len += 1;
+                    link = &node.next;
+                }
+                len
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns an iterator over the entries in bucket order.
+    pub fn iter(&self) -> impl Iterator<Item = (K, &T)> + '_ {
+        self.heads.iter().flat_map(|head| {
+            let mut link: &Option<Box<ChainNode<K, T>>> = head;
+            std::iter::from_fn(move || {
+                let node: &ChainNode<K, T> = link.as_ref()?;
+                link = &node.next;
+                Some((node.key, &node.value))
+            })
+        })
+    }
+
+    pub fn print(&self) {
+        println!(
+            "count {}, shift {}, cap {}, longest {}, load {}",
+            self.count,
+            self.shift,
+            self.heads.len(),
+            self.longest_chain(),
+            self.load_factor()
+        );
+    }
+}
+
+impl<T, K, H> Clone for ChainedTable<T, K, H>
+where
+    T: Default + Copy + Clone + Debug,
+    K: TableKey,
+    H: KeyHasher<K>,
+{
+    fn clone(&self) -> Self {
+        let heads: Vec<Option<Box<ChainNode<K, T>>>> = self
+            .heads
+            .iter()
+            .map(|head| {
+                let mut copy: Option<Box<ChainNode<K, T>>> = None;
+                let mut tail: &mut Option<Box<ChainNode<K, T>>> = &mut copy;
+                let mut link: &Option<Box<ChainNode<K, T>>> = head;
+                while let Some(node) = link {
+                    let added: &mut Box<ChainNode<K, T>> = tail.insert(Box::new(ChainNode {
+                        key: node.key,
+                        value: node.value,
+                        next: None,
+                    }));
+                    tail = &mut added.next;
+                    link = &node.next;
+                }
+                copy
+            })
+            .collect();
+        ChainedTable {
+            count: self.count,
+            shift: self.shift,
+            max_load: self.max_load,
+            hasher: self.hasher.clone(),
+            heads,
+        }
+    }
+}
+
+impl<T, K, H> Debug for ChainedTable<T, K, H>
+where
+    T: Default + Copy + Clone + Debug,
+    K: TableKey,
+    H: KeyHasher<K>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChainedTable")
+            .field("count", &self.count)
+            .field("shift", &self.shift)
+            .field("max_load", &self.max_load)
+            .field("hasher", &self.hasher)
+            .field("entries", &DebugEntries(self))
+            .finish()
+    }
+}
+
+struct DebugEntries<'a, T, K, H>(&'a ChainedTable<T, K, H>)
+where
+    T: Default + Copy + Clone + Debug,
+    K: TableKey,
+    H: KeyHasher<K>;
+
+impl<T, K, H> Debug for DebugEntries<'_, T, K, H>
+where
+    T: Default + Copy + Clone + Debug,
+    K: TableKey,
+    H: KeyHasher<K>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.0.iter()).finish()
+    }
+}
+
+impl<T, K, H> Drop for ChainedTable<T, K, H>
+where
+    T: Default + Copy + Clone + Debug,
+    K: TableKey,
+    H: KeyHasher<K>,
+{
+    fn drop(&mut self) {
+        for head in self.heads.iter_mut() {
+            let mut link: Option<Box<ChainNode<K, T>>> = head.take();
+            while let Some(mut node) = link {
+                link = node.next.take();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChainedTable;
+    use crate::hash3::hash::Identity;
+    use std::thread;
+
+    /// Small keys all have home bucket 0 under `Identity`, so they form one chain.
+    fn one_chain(len: usize) -> ChainedTable<u8, usize, Identity> {
+        let mut table: ChainedTable<u8, usize, Identity> =
+            ChainedTable::with_max_load_and_hasher(16, 1.0, Identity);
+        for key in 0..len {
+            table.put(key, 0);
+        }
+        table
+    }
+
+    #[test]
+    fn long_chains_do_not_overflow_the_stack() {
+        let table: ChainedTable<u8, usize, Identity> = one_chain(20_000);
+        assert_eq!(table.longest_chain(), 20_000);
+        thread::Builder::new()
+            .stack_size(64 << 10)
+            .spawn(move || {
+                let copy: ChainedTable<u8, usize, Identity> = table.clone();
+                assert_eq!(copy.len(), 20_000);
+                assert_eq!(copy.longest_chain(), 20_000);
+                assert!(format!("{:?}", copy).len() > 20_000);
+                drop(copy);
+                drop(table);
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+}
@@ -0,0 +1,235 @@
+use crate::hash3::hash::HASH_MULTIPLIER;
+
+/// Marks an empty bucket in place of the null `next` pointer used by `HashTable`.
+const EMPTY: usize = usize::MAX;
+
+/// Returns the capacity `HashTable::with_capacity` would choose for `entries` keys: the
+/// smallest power of two greater than `entries`, and at least 2.
+pub const fn capacity_for(entries: usize) -> usize {
+    let bits: usize = (usize::BITS - entries.leading_zeros()) as usize;
+    if bits == 0 {
+        2
+    } else {
+        1 << bits
+    }
+}
+
+/// The golden ratio hash `HashTable` uses for `usize` keys when it is not given a seed.
+#[inline]
+const fn fibonacci(key: usize, shift: usize) -> usize {
+    key.wrapping_mul(HASH_MULTIPLIER) >> shift
+}
+
+#[derive(Clone, Copy, Debug)]
+struct ConstBucket<T>
+where
+    T: Copy,
+{
+    next: usize,
+    key: usize,
+    value: Option<T>,
+}
+
+/// A read-only table of `N` buckets built by a `const fn`, so it can initialize a
+/// `static` with no work at startup.
+///
+/// Buckets hold the same circular chains as `HashTable`, linked by index instead of by
+/// pointer, and keys are placed with the same Fibonacci hash, so `get` walks exactly the
+/// chain `HashTable::get` would. `N` must be a power of two no smaller than the number of
+/// entries; `capacity_for` gives the size `HashTable` would use.
+///
+/// A table is declared as, for example,
+/// `static CODES: ConstHashTable<u16, { capacity_for(ENTRIES.len()) }> =
+/// ConstHashTable::new(ENTRIES);` with `ENTRIES` a `const` slice of key and value pairs.
+#[derive(Clone, Copy, Debug)]
+pub struct ConstHashTable<T, const N: usize>
+where
+    T: Copy,
+{
+    count: usize,
+    shift: usize,
+    buckets: [ConstBucket<T>; N],
+}
+
+impl<T, const N: usize> ConstHashTable<T, N>
+where
+    T: Copy,
+{
+    /// Builds the table from `entries`. Called in a constant context, a duplicate key or a
+    /// capacity that is too small fails the build; called at runtime, it panics.
+    ///
+    /// ```compile_fail
+    /// use rusty_buckets::const_table::ConstHashTable;
+    /// static TWICE: ConstHashTable<u8, 4> = ConstHashTable::new(&[(1, 1), (1, 2)]);
+    /// ```
+    pub const fn new(entries: &[(usize, T)]) -> Self {
+        assert!(
+            N.is_power_of_two() && N >= 2,
+            "Capacity must be a power of two"
+        );
+        assert!(entries.len() <= N, "Too many entries for the capacity");
+        let shift: usize = N.leading_zeros() as usize + 1;
+        let mut buckets: [ConstBucket<T>; N] = [ConstBucket {
+            next: EMPTY,
+            key: 0,
+            value: None,
+        }; N];
+
+        // Every key whose home bucket is still free claims it first. All remaining keys
+        // then join the chain of a home that is already occupied by its own key, so no
+        // bucket ever has to be moved out of the way as `HashTable::put` does.
+        let mut placed: [bool; N] = [false; N];
+        let mut i: usize = 0;
+        while i < entries.len() {
+            let (key, value): (usize, T) = entries[i];
+            let home: usize = fibonacci(key, shift);
+            if buckets[home].next == EMPTY {
+                buckets[home] = ConstBucket {
+                    next: home,
+                    key,
+                    value: Some(value),
+                };
+                placed[i] = true;
+            } else {
+                assert!(buckets[home].key != key, "Duplicate key");
+            }
+            i += 1;
+        }
+
+        let mut i: usize = 0;
+        while i < entries.len() {
+            let (key, value): (usize, T) = entries[i];
+            let home: usize = fibonacci(key, shift);
+            if placed[i] {
+                i += 1;
+                continue;
+            }
+            let mut tail: usize = home;
+            loop {
+                assert!(buckets[tail].key != key, "Duplicate key");
+                if buckets[tail].next == home {
+                    break;
+                }
+                tail = buckets[tail].next;
+            }
+            let mut slot: usize = home;
+            let mut probe: usize = 1;
+            while buckets[slot].next != EMPTY {
+                slot = (slot + probe) & (N - 1);
+                probe += 1;
+            }
+            buckets[slot] = ConstBucket {
+                next: home,
+                key,
+                value: Some(value),
+            };
+            buckets[tail].next = slot;
+            i += 1;
+        }
+
+        ConstHashTable {
+            count: entries.len(),
+            shift,
+            buckets,
+        }
+    }
+
+    #[inline]
+    pub const fn len(&self) -> usize {
+        self.count
+    }
+
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Looks up `key`. This is a `const fn` too, so tables can also be queried while
+    /// building other constants.
+    #[inline]
+    pub const fn get(&self, key: usize) -> Option<&T> {
+        let origin: usize = fibonacci(key, self.shift);
+        let mut index: usize = origin;
+        if self.buckets[origin].next == EMPTY {
+            return None;
+        }
+        loop {
+            let bucket: &ConstBucket<T> = &self.buckets[index];
+            if bucket.key == key {
+                return bucket.value.as_ref();
+            }
+            index = bucket.next;
+            if index == origin {
+                return None;
+            }
+        }
+    }
+
+    /// Returns an iterator over the entries in bucket order.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> + '_ {
+        self.buckets
+            .iter()
+            .filter_map(|bucket| bucket.value.as_ref().map(|value| (bucket.key, value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{capacity_for, ConstHashTable};
+
+    const ENTRIES: &[(usize, u16)] = &[(0, 10), (1, 11), (7, 17), (64, 164), (1 << 40, 40)];
+
+    static TABLE: ConstHashTable<u16, { capacity_for(ENTRIES.len()) }> =
+        ConstHashTable::new(ENTRIES);
+
+    /// Looked up while building a constant, as `get` is a `const fn`.
+    const SEVEN: Option<&u16> = TABLE.get(7);
+
+    #[test]
+    fn static_table_finds_its_entries() {
+        assert_eq!(TABLE.len(), ENTRIES.len());
+        assert_eq!(TABLE.capacity(), 8);
+        for (key, value) in ENTRIES {
+            assert_eq!(TABLE.get(*key), Some(value));
+        }
+        assert_eq!(SEVEN, Some(&17));
+        assert_eq!(TABLE.iter().count(), ENTRIES.len());
+    }
+
+    #[test]
+    fn static_table_misses() {
+        for key in [2, 3, 63, 65, usize::MAX] {
+            assert_eq!(TABLE.get(key), None);
+        }
+        static EMPTY: ConstHashTable<u16, 2> = ConstHashTable::new(&[]);
+        assert!(EMPTY.is_empty());
+        assert_eq!(EMPTY.get(0), None);
+    }
+
+    #[test]
+    fn table_may_be_completely_full() {
+        let entries: [(usize, usize); 16] = std::array::from_fn(|i| (i * 1000, i));
+        let table: ConstHashTable<usize, 16> = ConstHashTable::new(&entries);
+        for (key, value) in entries.iter() {
+            assert_eq!(table.get(*key), Some(value));
+        }
+        assert_eq!(table.get(1), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Duplicate key")]
+    fn rejects_duplicate_keys() {
+        ConstHashTable::<u8, 8>::new(&[(1, 1), (2, 2), (1, 3)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Too many entries for the capacity")]
+    fn rejects_too_many_entries() {
+        ConstHashTable::<u8, 2>::new(&[(1, 1), (2, 2), (3, 3)]);
+    }
+}
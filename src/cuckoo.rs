@@ -0,0 +1,332 @@
+use crate::hash3::hash::{Fibonacci, HashSeed, Key, KeyHasher, TableKey, USIZE_BITS};
+use std::fmt::Debug;
+use std::mem;
+
+/// The number of keys held by each bucket.
+const SLOTS: usize = 4;
+
+/// The number of keys that can wait in the stash before the table is rebuilt.
+const STASH: usize = 4;
+
+/// The number of keys moved by one insert before it gives up and uses the stash.
+const MAX_KICKS: usize = 256;
+
+/// The seed of the second hash function of tables that are not given one.
+const SECOND_SEED: u64 = 0x2545F4914F6CDD1D;
+
+#[derive(Clone, Copy, Debug, Default)]
+struct CuckooBucket<K, T>
+where
+    K: TableKey,
+    T: Default + Copy + Clone + Debug,
+{
+    len: usize,
+    keys: [K; SLOTS],
+    values: [T; SLOTS],
+}
+
+/// Cuckoo hashing with buckets of four keys and a small stash, for lookups with a
+/// worst-case bound.
+///
+/// Every key lives in one of two buckets, chosen by two hash functions, or in the stash.
+/// A `get` therefore compares at most `2 * 4 + 4` keys whatever the load and whatever the
+/// keys, where `HashTable::get` walks a chain of unbounded length. An insert that finds
+/// both buckets full evicts a random key to its other bucket, and so on for up to 256
+/// moves; a key still left over goes to the stash. When the stash is full too the table is
+/// rebuilt, with fresh seeds below half load and at twice the size above it.
+///
+/// By default the first hash function is the plain Fibonacci hash `HashTable` uses and
+/// the second is the same multiplication randomized by a second seed.
+#[derive(Clone, Debug)]
+pub struct CuckooTable<T, K = usize, H = Fibonacci>
+where
+    T: Default + Copy + Clone + Debug,
+    K: TableKey,
+    H: KeyHasher<K>,
+{
+    count: usize,
+    shift: usize,
+    rehashes: usize,
+    hashers: [H; 2],
+    buckets: Vec<CuckooBucket<K, T>>,
+    stash: Vec<(K, T)>,
+}
+
+impl<T, K> CuckooTable<T, K, Fibonacci>
+where
+    T: Default + Copy + Clone + Debug,
+    K: Key,
+{
+    pub fn with_capacity(initial_capacity: usize) -> Self {
+        Self::with_capacity_and_seed(initial_capacity, HashSeed::from_u64(SECOND_SEED))
+    }
+
+    /// Creates a table whose second hash function uses `seed`.
+    pub fn with_capacity_and_seed(initial_capacity: usize, seed: HashSeed) -> Self {
+        Self::with_capacity_and_hashers(
+            initial_capacity,
+            [Fibonacci::default(), Fibonacci::with_seed(seed)],
+        )
+    }
+}
+
+impl<T, K, H> CuckooTable<T, K, H>
+where
+    T: Default + Copy + Clone + Debug,
+    K: TableKey,
+    H: KeyHasher<K>,
+{
+    pub const BUCKET_SIZE: usize = mem::size_of::<CuckooBucket<K, T>>();
+    pub const MIN_BITS: usize = 1;
+
+    /// Creates a table with room for `initial_capacity` keys. The two hashers must differ,
+    /// or every key has the same bucket twice.
+    pub fn with_capacity_and_hashers(initial_capacity: usize, hashers: [H; 2]) -> Self {
+        let buckets: usize = initial_capacity.div_ceil(SLOTS);
+        let bits: usize = (USIZE_BITS - buckets.leading_zeros() as usize).max(Self::MIN_BITS);
+        Self::allocate(bits, hashers)
+    }
+
+    fn allocate(bits: usize, hashers: [H; 2]) -> Self {
+        assert!(bits < USIZE_BITS, "Allocation too large");
+        CuckooTable {
+            count: 0,
+            shift: USIZE_BITS - bits,
+            rehashes: 0,
+            hashers,
+            buckets: vec![CuckooBucket::default(); 1 << bits],
+            stash: Vec::with_capacity(STASH),
+        }
+    }
+
+    /// Returns the two buckets `key` may live in.
+    #[inline]
+    fn buckets_of(&self, key: K) -> [usize; 2] {
+        [
+            self.hashers[0].hash(key) >> self.shift,
+            self.hashers[1].hash(key) >> self.shift,
+        ]
+    }
+
+    /// Returns true if the load factor is greater than or equal to 0.875.
+    #[inline]
+    fn should_grow(&self) -> bool {
+        let capacity: usize = self.capacity();
+        self.count >= (capacity - (capacity >> 3))
+    }
+
+    #[inline]
+    pub fn load_factor(&self) -> f64 {
+        self.count as f64 / self.capacity() as f64
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Returns the number of keys the buckets can hold, not counting the stash.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.buckets.len() * SLOTS
+    }
+
+    /// Returns the number of bytes allocated for buckets and the stash.
+    #[inline]
+    pub fn allocated_bytes(&self) -> usize {
+        self.buckets.len() * Self::BUCKET_SIZE + STASH * mem::size_of::<(K, T)>()
+    }
+
+    /// Returns the number of keys waiting in the stash.
+    #[inline]
+    pub fn stashed(&self) -> usize {
+        self.stash.len()
+    }
+
+    /// Returns the number of times the table was rebuilt with fresh seeds.
+    #[inline]
+    pub fn rehashes(&self) -> usize {
+        self.rehashes
+    }
+
+    #[inline]
+    pub fn hashers(&self) -> &[H; 2] {
+        &self.hashers
+    }
+
+    #[inline]
+    pub fn get(&self, key: K) -> Option<&T> {
+        for index in self.buckets_of(key) {
+            let bucket: &CuckooBucket<K, T> = &self.buckets[index];
+            for slot in 0..bucket.len {
+                if bucket.keys[slot] == key {
+                    return Some(&bucket.values[slot]);
+                }
+            }
+        }
+        self.stash
+            .iter()
+            .find(|(stashed, _)| *stashed == key)
+            .map(|(_, value)| value)
+    }
+
+    fn get_mut(&mut self, key: K) -> Option<&mut T> {
+        for index in self.buckets_of(key) {
+            let bucket: &CuckooBucket<K, T> = &self.buckets[index];
+            if let Some(slot) = bucket.keys[..bucket.len].iter().position(|k| *k == key) {
+                return Some(&mut self.buckets[index].values[slot]);
+            }
+        }
+        self.stash
+            .iter_mut()
+            .find(|(stashed, _)| *stashed == key)
+            .map(|(_, value)| value)
+    }
+
+    pub fn put(&mut self, key: K, value: T) -> Option<T> {
+        if let Some(old) = self.get_mut(key) {
+            return Some(mem::replace(old, value));
+        }
+        if self.should_grow() {
+            self.grow();
+        }
+        if let Err(pending) = self.insert(key, value) {
+            let bits: usize = USIZE_BITS - self.shift;
+            let mut entries: Vec<(K, T)> = self.entries();
+            entries.push(pending);
+            if self.load_factor() < 0.5 {
+                self.rebuild(bits, true, entries);
+            } else {
+                self.rebuild(bits + 1, false, entries);
+            }
+        } else {
+            self.count += 1;
+        }
+        None
+    }
+
+    /// Removes `key`, filling its slot with the last key of the bucket.
+    pub fn remove(&mut self, key: K) -> Option<T> {
+        for index in self.buckets_of(key) {
+            let bucket: &mut CuckooBucket<K, T> = &mut self.buckets[index];
+            if let Some(slot) = bucket.keys[..bucket.len].iter().position(|k| *k == key) {
+                let value: T = bucket.values[slot];
+                bucket.len -= 1;
+                bucket.keys[slot] = bucket.keys[bucket.len];
+                bucket.values[slot] = bucket.values[bucket.len];
+                self.count -= 1;
+                return Some(value);
+            }
+        }
+        let slot: usize = self.stash.iter().position(|(stashed, _)| *stashed == key)?;
+        self.count -= 1;
+        Some(self.stash.swap_remove(slot).1)
+    }
+
+    /// Places a key that is not in the table, or returns the key left over when both the
+    /// eviction walk and the stash are exhausted. The count is left to the caller.
+    fn insert(&mut self, key: K, value: T) -> Result<(), (K, T)> {
+        let [first, second]: [usize; 2] = self.buckets_of(key);
+        if self.push(first, key, value) || self.push(second, key, value) {
+            return Ok(());
+        }
+        let mut carried: (K, T) = (key, value);
+        let mut index: usize = if rand::random::<bool>() {
+            first
+        } else {
+            second
+        };
+        for _ in 0..MAX_KICKS {
+            let slot: usize = rand::random::<usize>() % SLOTS;
+            let bucket: &mut CuckooBucket<K, T> = &mut self.buckets[index];
+            mem::swap(&mut carried.0, &mut bucket.keys[slot]);
+            mem::swap(&mut carried.1, &mut bucket.values[slot]);
+            let [first, second]: [usize; 2] = self.buckets_of(carried.0);
+            index = if index == first { second } else { first };
+            if self.push(index, carried.0, carried.1) {
+                return Ok(());
+            }
+        }
+        if self.stash.len() < STASH {
+            self.stash.push(carried);
+            Ok(())
+        } else {
+            Err(carried)
+        }
+    }
+
+    /// Appends a key to a bucket with a free slot and returns true, or returns false if
+    /// the bucket is full.
+    #[inline]
+    fn push(&mut self, index: usize, key: K, value: T) -> bool {
+        let bucket: &mut CuckooBucket<K, T> = &mut self.buckets[index];
+        if bucket.len == SLOTS {
+            return false;
+        }
+        bucket.keys[bucket.len] = key;
+        bucket.values[bucket.len] = value;
+        bucket.len += 1;
+        true
+    }
+
+    fn entries(&self) -> Vec<(K, T)> {
+        self.iter().map(|(key, value)| (key, *value)).collect()
+    }
+
+    fn grow(&mut self) {
+        let bits: usize = USIZE_BITS - self.shift + 1;
+        let entries: Vec<(K, T)> = self.entries();
+        self.rebuild(bits, false, entries);
+    }
+
+    /// Rebuilds the table with `2^bits` buckets, with fresh seeds if `reseed` is set,
+    /// until every entry fits. Hashers without a seed double the size instead.
+    fn rebuild(&mut self, mut bits: usize, mut reseed: bool, entries: Vec<(K, T)>) {
+        loop {
+            let mut hashers: [H; 2] = self.hashers.clone();
+            if reseed {
+                self.rehashes += 1;
+                if !(hashers[0].reseed() & hashers[1].reseed()) {
+                    bits += 1;
+                }
+            }
+            let rehashes: usize = self.rehashes;
+            *self = Self::allocate(bits, hashers);
+            self.rehashes = rehashes;
+            if entries
+                .iter()
+                .all(|(key, value)| self.insert(*key, *value).is_ok())
+            {
+                self.count = entries.len();
+                return;
+            }
+            reseed = true;
+        }
+    }
+
+    /// Returns an iterator over the entries in bucket order, followed by the stash.
+    pub fn iter(&self) -> impl Iterator<Item = (K, &T)> + '_ {
+        self.buckets
+            .iter()
+            .flat_map(|bucket| bucket.keys[..bucket.len].iter().zip(&bucket.values))
+            .chain(self.stash.iter().map(|(key, value)| (key, value)))
+            .map(|(key, value)| (*key, value))
+    }
+
+    pub fn print(&self) {
+        println!(
+            "count {}, shift {}, buckets {}, stash {}, rehashes {}, load {}",
+            self.count,
+            self.shift,
+            self.buckets.len(),
+            self.stash.len(),
+            self.rehashes,
+            self.load_factor()
+        );
+    }
+}
@@ -0,0 +1,430 @@
+use crate::hash3::hash::map::HashTable;
+use crate::hash3::hash::{mix64, HashCache, Key, KeyHasher, ProbeStrategy, SplitMix64, USIZE_BITS};
+use std::fmt::Debug;
+
+/// The number of levels tried before giving up. With the default gamma the chance of
+/// needing more than a dozen levels is negligible, so reaching this limit means the
+/// keys were not distinct.
+const MAX_LEVELS: usize = 64;
+
+/// The number of bit words covered by each entry of the rank index.
+const RANK_WORDS: usize = 8;
+
+/// A minimal perfect hash function over a fixed key set, built BBHash style.
+///
+/// Each level is a bit array `gamma` times larger than the keys still unplaced. A key is
+/// hashed to one bit per level; keys that land alone on a bit claim it, and keys that
+/// collide move on to the next, smaller level. The index of a key is the number of set
+/// bits before its bit across all levels, so the `n` keys map onto `0..n` exactly.
+///
+/// Keys outside the set still map to some index, or to `None`; callers that need to
+/// reject them must compare against the key stored at that index.
+#[derive(Clone, Debug)]
+pub struct PerfectHash {
+    seed: u64,
+    /// The length in bits of each level; always a multiple of 64.
+    levels: Box<[usize]>,
+    bits: Box<[u64]>,
+    /// `ranks[i]` is the number of set bits in `bits[..i * RANK_WORDS]`.
+    ranks: Box<[usize]>,
+    len: usize,
+}
+
+impl PerfectHash {
+    fn build<K: Key>(keys: &[K], gamma: f64, seed: u64) -> Self {
+        let mut sorted: Vec<u128> = keys.iter().map(|key| key.to_bits()).collect();
+        sorted.sort_unstable();
+        assert!(
+            sorted.windows(2).all(|pair| pair[0] != pair[1]),
+            "Duplicate key"
+        );
+
+        let mut levels: Vec<usize> = Vec::new();
+        let mut bits: Vec<u64> = Vec::new();
+        let mut remaining: Vec<K> = keys.to_vec();
+        while !remaining.is_empty() {
+            assert!(levels.len() < MAX_LEVELS, "Too many levels");
+            let level: usize = levels.len();
+            let len: usize =
+                ((remaining.len() as f64 * gamma).ceil() as usize).next_multiple_of(64);
+            let mut taken: Vec<u64> = vec![0; len / 64];
+            let mut collided: Vec<u64> = vec![0; len / 64];
+            for key in remaining.iter() {
+                let bit: usize = Self::position(seed, level, len, *key);
+                if taken[bit / 64] & (1 << (bit % 64)) != 0 {
+                    collided[bit / 64] |= 1 << (bit % 64);
+                } else {
+                    taken[bit / 64] |= 1 << (bit % 64);
+                }
+            }
+            remaining.retain(|key| {
+                let bit: usize = Self::position(seed, level, len, *key);
+                collided[bit / 64] & (1 << (bit % 64)) != 0
+            });
+            bits.extend(taken.iter().zip(collided.iter()).map(|(t, c)| t & !c));
+            levels.push(len);
+        }
+
+        Self::from_parts(seed, levels.into_boxed_slice(), bits.into_boxed_slice())
+            .expect("Levels are consistent")
+    }
+
+    /// Rebuilds the rank index over `bits`, or returns `None` if the level lengths do not
+    /// describe `bits`.
+    fn from_parts(seed: u64, levels: Box<[usize]>, bits: Box<[u64]>) -> Option<Self> {
+        let mut words: usize = 0;
+        for len in levels.iter() {
+            if *len == 0 || !len.is_multiple_of(64) {
+                return None;
+            }
+            words = words.checked_add(len / 64)?;
+        }
+        if words != bits.len() {
+            return None;
+        }
+        let mut ranks: Vec<usize> = Vec::with_capacity(bits.len().div_ceil(RANK_WORDS));
+        let mut len: usize = 0;
+        for block in bits.chunks(RANK_WORDS) {
+            ranks.push(len);
+            len += block
+                .iter()
+                .map(|word| word.count_ones() as usize)
+                .sum::<usize>();
+        }
+        Some(PerfectHash {
+            seed,
+            levels,
+            bits,
+            ranks: ranks.into_boxed_slice(),
+            len,
+        })
+    }
+
+    #[inline]
+    fn position<K: Key>(seed: u64, level: usize, len: usize, key: K) -> usize {
+        let hash: usize = SplitMix64::with_seed(mix64(seed ^ level as u64)).hash(key);
+        ((hash as u128 * len as u128) >> USIZE_BITS) as usize
+    }
+
+    /// Returns the number of keys the function was built for.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the size of the function in bits per key, rank index included.
+    pub fn bits_per_key(&self) -> f64 {
+        if self.len == 0 {
+            0.0
+        } else {
+            (self.bits.len() * 64 + self.ranks.len() * USIZE_BITS) as f64 / self.len as f64
+        }
+    }
+
+    /// Returns the index in `0..len` of a key from the set.
+    #[inline]
+    pub fn index<K: Key>(&self, key: K) -> Option<usize> {
+        let mut start: usize = 0;
+        for (level, len) in self.levels.iter().enumerate() {
+            let bit: usize = start + Self::position(self.seed, level, *len, key);
+            let word: u64 = self.bits[bit / 64];
+            if word & (1 << (bit % 64)) != 0 {
+                let block: usize = bit / 64 / RANK_WORDS;
+                let below: usize = self.bits[block * RANK_WORDS..bit / 64]
+                    .iter()
+                    .map(|word| word.count_ones() as usize)
+                    .sum::<usize>()
+                    + (word & ((1 << (bit % 64)) - 1)).count_ones() as usize;
+                return Some(self.ranks[block] + below);
+            }
+            start += len;
+        }
+        None
+    }
+}
+
+/// Builds `PerfectHash` functions and `FrozenMap`s.
+///
+/// `gamma` trades space for build time: each level has `gamma` bits per key still to be
+/// placed. The default of 2 takes about 3.7 bits per key including the rank index,
+/// against 3.1 for a gamma of 1, but leaves fewer keys to each later level, so building
+/// and lookups are faster.
+#[derive(Clone, Copy, Debug)]
+pub struct FrozenMapBuilder {
+    gamma: f64,
+    seed: u64,
+}
+
+impl Default for FrozenMapBuilder {
+    fn default() -> Self {
+        FrozenMapBuilder {
+            gamma: 2.0,
+            seed: 0,
+        }
+    }
+}
+
+impl FrozenMapBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn gamma(mut self, gamma: f64) -> Self {
+        assert!(gamma >= 1.0, "Gamma must be at least 1");
+        self.gamma = gamma;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Builds a minimal perfect hash function for `keys`, which must be distinct.
+    pub fn build_hash<K: Key>(&self, keys: &[K]) -> PerfectHash {
+        PerfectHash::build(keys, self.gamma, self.seed)
+    }
+
+    /// Builds a map from `entries`, whose keys must be distinct.
+    pub fn build<T, K, I>(&self, entries: I) -> FrozenMap<T, K>
+    where
+        T: Default + Copy + Clone + Debug,
+        K: Key,
+        I: IntoIterator<Item = (K, T)>,
+    {
+        let entries: Vec<(K, T)> = entries.into_iter().collect();
+        let keys: Vec<K> = entries.iter().map(|(key, _)| *key).collect();
+        let hash: PerfectHash = self.build_hash(&keys);
+        let mut ordered_keys: Vec<K> = vec![K::default(); entries.len()];
+        let mut values: Vec<T> = vec![T::default(); entries.len()];
+        for (key, value) in entries {
+            let index: usize = hash.index(key).expect("Key is in the set");
+            ordered_keys[index] = key;
+            values[index] = value;
+        }
+        FrozenMap {
+            hash,
+            keys: ordered_keys.into_boxed_slice(),
+            values: values.into_boxed_slice(),
+        }
+    }
+
+    /// Freezes the contents of `table`.
+    pub fn from_table<T, K, H, C, P>(&self, table: &HashTable<T, K, H, C, P>) -> FrozenMap<T, K>
+    where
+        T: Default + Copy + Clone + Debug,
+        K: Key,
+        H: KeyHasher<K>,
+        C: HashCache,
+        P: ProbeStrategy,
+    {
+        self.build(table.iter().map(|(key, value)| (key, *value)))
+    }
+}
+
+/// An immutable map over a fixed key set, looked up through a `PerfectHash`.
+///
+/// Keys and values are stored densely in the order given by the hash, so there are no
+/// empty slots and a `get` reads exactly one key and one value after evaluating the hash.
+#[derive(Clone, Debug)]
+pub struct FrozenMap<T, K = usize>
+where
+    T: Default + Copy + Clone + Debug,
+    K: Key,
+{
+    hash: PerfectHash,
+    keys: Box<[K]>,
+    values: Box<[T]>,
+}
+
+impl<T, K> FrozenMap<T, K>
+where
+    T: Default + Copy + Clone + Debug,
+    K: Key,
+{
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn hash(&self) -> &PerfectHash {
+        &self.hash
+    }
+
+    #[inline]
+    pub fn get(&self, key: K) -> Option<&T> {
+        let index: usize = self.hash.index(key)?;
+        if *self.keys.get(index)? == key {
+            self.values.get(index)
+        } else {
+            None
+        }
+    }
+
+    /// Returns an iterator over the entries in hash order.
+    pub fn iter(&self) -> impl Iterator<Item = (K, &T)> + '_ {
+        self.keys.iter().copied().zip(self.values.iter())
+    }
+}
+
+impl<T, K> From<&HashTable<T, K>> for FrozenMap<T, K>
+where
+    T: Default + Copy + Clone + Debug,
+    K: Key,
+{
+    fn from(table: &HashTable<T, K>) -> Self {
+        FrozenMapBuilder::new().from_table(table)
+    }
+}
+
+/// A frozen map serializes its hash function along with the entries, so deserializing it
+/// only rebuilds the rank index instead of searching for a new function.
+#[cfg(feature = "serde")]
+impl<T, K> serde::Serialize for FrozenMap<T, K>
+where
+    T: Default + Copy + Clone + Debug + serde::Serialize,
+    K: Key + serde::Serialize,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeTuple;
+        let mut tuple = serializer.serialize_tuple(5)?;
+        tuple.serialize_element(&self.hash.seed)?;
+        tuple.serialize_element(&self.hash.levels)?;
+        tuple.serialize_element(&self.hash.bits)?;
+        tuple.serialize_element(&self.keys)?;
+        tuple.serialize_element(&self.values)?;
+        tuple.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T, K> serde::Deserialize<'de> for FrozenMap<T, K>
+where
+    T: Default + Copy + Clone + Debug + serde::Deserialize<'de>,
+    K: Key + serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        type Parts<T, K> = (u64, Box<[usize]>, Box<[u64]>, Box<[K]>, Box<[T]>);
+        let (seed, levels, bits, keys, values): Parts<T, K> =
+            serde::Deserialize::deserialize(deserializer)?;
+        let hash: PerfectHash = PerfectHash::from_parts(seed, levels, bits)
+            .filter(|hash| hash.len() == keys.len() && keys.len() == values.len())
+            .ok_or_else(|| serde::de::Error::custom("inconsistent frozen map"))?;
+        Ok(FrozenMap { hash, keys, values })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FrozenMap, FrozenMapBuilder, PerfectHash};
+    use crate::hash3::hash::map::HashTable;
+    use crate::hash3::hash::mix64;
+
+    fn entries_u64(count: u64) -> Vec<(u64, u64)> {
+        (0..count)
+            .map(|i| (i.wrapping_mul(0x9E3779B97F4A7C15), i))
+            .collect()
+    }
+
+    #[test]
+    fn maps_u64_keys_onto_their_indices() {
+        let entries: Vec<(u64, u64)> = entries_u64(10_000);
+        let keys: Vec<u64> = entries.iter().map(|(key, _)| *key).collect();
+        let hash: PerfectHash = FrozenMapBuilder::new().build_hash(&keys);
+        assert_eq!(hash.len(), keys.len());
+        let mut seen: Vec<bool> = vec![false; keys.len()];
+        for key in keys.iter() {
+            let index: usize = hash.index(*key).unwrap();
+            assert!(!seen[index]);
+            seen[index] = true;
+        }
+
+        let map: FrozenMap<u64, u64> = FrozenMapBuilder::new().build(entries.iter().copied());
+        assert_eq!(map.len(), entries.len());
+        for (key, value) in entries.iter() {
+            assert_eq!(map.get(*key), Some(value));
+        }
+    }
+
+    #[test]
+    fn maps_u128_keys() {
+        let entries: Vec<(u128, u64)> = (0..5_000u64)
+            .map(|i| (((i as u128) << 64) | (i * 3) as u128, i))
+            .collect();
+        let map: FrozenMap<u64, u128> = FrozenMapBuilder::new()
+            .gamma(1.0)
+            .build(entries.iter().copied());
+        for (key, value) in entries.iter() {
+            assert_eq!(map.get(*key), Some(value));
+        }
+        assert_eq!(map.get(1), None);
+        assert_eq!(map.get(1 << 64), None);
+    }
+
+    #[test]
+    fn misses_keys_outside_the_set() {
+        let map: FrozenMap<u64, u64> = FrozenMapBuilder::new().build(entries_u64(1_000));
+        for key in 1..1_000u64 {
+            assert_eq!(map.get(key), None);
+        }
+        let empty: FrozenMap<u64, u64> = FrozenMapBuilder::new().build(Vec::new());
+        assert!(empty.is_empty());
+        assert_eq!(empty.get(0), None);
+    }
+
+    #[test]
+    fn separates_u128_keys_whose_halves_fold_alike() {
+        // Mixing the upper half into the lower one without the seed folds these two keys
+        // to the same word, which no level seed could separate.
+        let (hi1, lo1): (u64, u64) = (1, 2);
+        let hi2: u64 = 3;
+        let lo2: u64 = lo1 ^ mix64(hi1) ^ mix64(hi2);
+        let first: u128 = ((hi1 as u128) << 64) | lo1 as u128;
+        let second: u128 = ((hi2 as u128) << 64) | lo2 as u128;
+        let map: FrozenMap<u8, u128> = FrozenMapBuilder::new().build([(first, 1), (second, 2)]);
+        assert_eq!(map.get(first), Some(&1));
+        assert_eq!(map.get(second), Some(&2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Duplicate key")]
+    fn rejects_duplicate_keys() {
+        FrozenMapBuilder::new().build([(1u64, 1u8), (1, 2)]);
+    }
+
+    #[test]
+    fn freezes_a_table() {
+        let mut table: HashTable<usize> = HashTable::with_capacity(16);
+        for key in 0..1_000usize {
+            table.put(key * 7, key);
+        }
+        let map: FrozenMap<usize> = FrozenMap::from(&table);
+        assert_eq!(map.len(), table.len());
+        assert!(table.iter().all(|(key, value)| map.get(key) == Some(value)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let map: FrozenMap<u64, u64> = FrozenMapBuilder::new().seed(7).build(entries_u64(1_000));
+        let json: String = serde_json::to_string(&map).unwrap();
+        let from_json: FrozenMap<u64, u64> = serde_json::from_str(&json).unwrap();
+        let bytes: Vec<u8> = bincode::serialize(&map).unwrap();
+        let from_bincode: FrozenMap<u64, u64> = bincode::deserialize(&bytes).unwrap();
+        for copy in [from_json, from_bincode] {
+            assert_eq!(copy.len(), map.len());
+            assert!(map.iter().all(|(key, value)| copy.get(key) == Some(value)));
+            assert_eq!(copy.get(1), None);
+        }
+    }
+}
@@ -8,7 +8,7 @@ pub mod hash {
     /// The correct multiplier constant for the hash function is based on the golden ratio.
     /// The golden ratio can be calculated with Python 3 using the following statements:
     ///
-    /// ```
+    /// ```text
     /// from decimal import Decimal
     /// golden_ratio = Decimal((Decimal(1.0) + Decimal.sqrt(Decimal(5.0)))/ Decimal(2.0))
     /// golden_ratio
@@ -36,7 +36,7 @@ pub mod hash {
         count >= (capacity - (capacity >> 4))
     }
 
-    /// Returns true if the load factor is less than or equal to 0.375.
+    // Returns true if the load factor is less than or equal to 0.375.
     /*#[inline]
     fn should_shrink(count: usize, capacity: usize) -> bool {
         count <= (capacity >> 2) + (capacity >> 3)
@@ -120,7 +120,7 @@ pub mod hash {
                         count: 0,
                         shift: capacity.leading_zeros() as usize + 1,
                         mask: capacity - 1,
-                        capacity: capacity as usize,
+                        capacity,
                         body: PhantomData,
                     });
 
@@ -134,6 +134,29 @@ pub mod hash {
                 HashTable { ptr }
             }
 
+            /// Returns the number of keys stored in the table.
+            #[inline]
+            pub fn len(&self) -> usize {
+                unsafe { (*self.ptr).count }
+            }
+
+            #[inline]
+            pub fn is_empty(&self) -> bool {
+                self.len() == 0
+            }
+
+            /// Returns an iterator over the occupied buckets in bucket order.
+            pub fn iter(&self) -> Iter<'_, T> {
+                unsafe {
+                    Iter {
+                        ptr: self.ptr.add(1) as *const Bucket<T>,
+                        index: 0,
+                        capacity: (*self.ptr).capacity,
+                        marker: PhantomData,
+                    }
+                }
+            }
+
             fn create_layout(capacity: usize) -> Layout {
                 assert!(mem::size_of::<T>() != 0, "Capacity overflow");
                 let head_layout: Layout = Layout::new::<InnerHashTable<T>>();
@@ -151,6 +174,9 @@ pub mod hash {
                     let h: usize = super::hash(key, (*self.ptr).shift);
                     let mut bucket: *mut Bucket<T> = (self.ptr.add(1) as *mut Bucket<T>).add(h);
                     let origin: *mut Bucket<T> = bucket;
+                    if (*origin).next.is_null() {
+                        return None;
+                    }
                     loop {
                         if (*bucket).key == key {
                             return Some(&(*bucket).value);
@@ -171,7 +197,7 @@ pub mod hash {
 
                     let new_cap: usize = 2 * old_capacity;
                     let new_layout: Layout = Self::create_layout(new_cap);
-                    let mut new_ptr: *mut InnerHashTable<T> =
+                    let new_ptr: *mut InnerHashTable<T> =
                         alloc::alloc(new_layout) as *mut InnerHashTable<T>;
 
                     if new_ptr.is_null() {
@@ -326,5 +352,81 @@ pub mod hash {
                 }
             }
         }
+
+        pub struct Iter<'a, T>
+        where
+            T: Default + Copy + Clone + Debug,
+        {
+            ptr: *const Bucket<T>,
+            index: usize,
+            capacity: usize,
+            marker: PhantomData<&'a T>,
+        }
+
+        impl<'a, T> Iterator for Iter<'a, T>
+        where
+            T: Default + Copy + Clone + Debug,
+        {
+            type Item = (usize, &'a T);
+
+            fn next(&mut self) -> Option<Self::Item> {
+                while self.index < self.capacity {
+                    let b: *const Bucket<T> = unsafe { self.ptr.add(self.index) };
+                    self.index += 1;
+                    unsafe {
+                        if !(*b).next.is_null() {
+                            return Some(((*b).key, &(*b).value));
+                        }
+                    }
+                }
+                None
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<T> serde::Serialize for HashTable<T>
+        where
+            T: Default + Copy + Clone + Debug + serde::Serialize,
+        {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeMap;
+                // `Iter` has no exact size hint, and formats such as bincode need the length
+                // up front.
+                let mut map = serializer.serialize_map(Some(self.len()))?;
+                for (key, value) in self.iter() {
+                    map.serialize_entry(&key, value)?;
+                }
+                map.end()
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de, T> serde::Deserialize<'de> for HashTable<T>
+        where
+            T: Default + Copy + Clone + Debug + serde::Deserialize<'de>,
+        {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer.deserialize_map(crate::serde_impl::TableVisitor::new(
+                    Self::with_capacity,
+                    |table: &mut Self, key, value| {
+                        table.put(key, value);
+                    },
+                ))
+            }
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::HashTable;
+
+            #[test]
+            fn get_stops_at_an_empty_home() {
+                let mut table: HashTable<usize> = HashTable::with_capacity(16);
+                table.put(1, 5);
+                // Key 0 lives in bucket 0, which is empty and holds the default key 0.
+                assert_eq!(table.get(0), None);
+                assert_eq!(table.get(1), Some(&5));
+            }
+        }
     }
 }
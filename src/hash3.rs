@@ -1,6 +1,8 @@
 pub mod hash {
 
-    const USIZE_BITS: usize = std::mem::size_of::<usize>() * 8;
+    use std::fmt::Debug;
+
+    pub(crate) const USIZE_BITS: usize = std::mem::size_of::<usize>() * 8;
 
     /// This function calculates the initial index into the hash table. It multiplies the key
     /// by a constant integral value equal to 2^64 divided by the golden ratio.
@@ -8,7 +10,7 @@ pub mod hash {
     /// The correct multiplier constant for the hash function is based on the golden ratio.
     /// The golden ratio can be calculated with Python 3 using the following statements:
     ///
-    /// ```
+    /// ```text
     /// from decimal import Decimal
     /// golden_ratio = Decimal((Decimal(1.0) + Decimal.sqrt(Decimal(5.0)))/ Decimal(2.0))
     /// golden_ratio
@@ -17,7 +19,8 @@ pub mod hash {
     ///
     /// For 64-bit values use 2^64 / golden_ratio = 11400714819323198486
     /// For 32-bit values use 2^32 / golden_ratio = 2654435769
-    const HASH_MULTIPLIER: usize = if USIZE_BITS == 64 {
+    /// For 128-bit values use 2^128 / golden_ratio = 210306068529402873165736369884012333108
+    pub(crate) const HASH_MULTIPLIER: usize = if USIZE_BITS == 64 {
         11400714819323198486
     } else if USIZE_BITS == 32 {
         2654435769
@@ -25,57 +28,565 @@ pub mod hash {
         panic!("Only 32-bit and 64-bit platforms are supported.")
     };
 
-    /// Returns true if the load factor is less than or equal to 0.375.
+    /// Any type a table can store as a key. Keys are compared with `Eq` and hashed by the
+    /// table's `KeyHasher`, so non-integer keys need a hasher of their own.
+    pub trait TableKey: Copy + Eq + Debug + Default {}
+
+    impl<K: Copy + Eq + Debug + Default> TableKey for K {}
+
+    /// An integer type that can be used as a table key. Each width carries its own golden
+    /// ratio multiplier, 2^BITS / golden_ratio rounded to the nearest integer, so Fibonacci
+    /// hashing does not depend on the width of `usize`. Signed keys hash their two's
+    /// complement bit pattern.
+    pub trait Key: Copy + Eq + Debug + Default {
+        const BITS: u32;
+        const MULTIPLIER: Self;
+
+        fn wrapping_mul(self, rhs: Self) -> Self;
+
+        fn rotate_left(self, n: u32) -> Self;
+
+        fn xor(self, rhs: Self) -> Self;
+
+        /// Keeps the low `BITS` bits of `bits`.
+        fn truncate(bits: u128) -> Self;
+
+        /// Returns the bit pattern of the key zero extended to 128 bits.
+        fn to_bits(self) -> u128;
+
+        /// Returns the top `usize::BITS` bits of the key, left aligned when the key is
+        /// narrower than a word. This is how a key sized hash becomes a table hash.
+        #[inline]
+        fn high_word(self) -> usize {
+            ((self.to_bits() << (128 - Self::BITS)) >> (128 - USIZE_BITS)) as usize
+        }
+    }
+
+    macro_rules! impl_key {
+        ($($key:ty => $bits:ty, $multiplier:expr;)*) => {
+            $(
+                impl Key for $key {
+                    const BITS: u32 = <$bits>::BITS;
+                    const MULTIPLIER: Self = $multiplier as $bits as $key;
+
+                    #[inline]
+                    fn wrapping_mul(self, rhs: Self) -> Self {
+                        <$key>::wrapping_mul(self, rhs)
+                    }
+
+                    #[inline]
+                    fn rotate_left(self, n: u32) -> Self {
+                        <$key>::rotate_left(self, n)
+                    }
+
+                    #[inline]
+                    fn xor(self, rhs: Self) -> Self {
+                        self ^ rhs
+                    }
+
+                    #[inline]
+                    fn truncate(bits: u128) -> Self {
+                        bits as $bits as $key
+                    }
+
+                    #[inline]
+                    fn to_bits(self) -> u128 {
+                        self as $bits as u128
+                    }
+                }
+            )*
+        };
+    }
+
+    impl_key! {
+        u32 => u32, 2654435769u32;
+        u64 => u64, 11400714819323198486u64;
+        u128 => u128, 210306068529402873165736369884012333108u128;
+        usize => usize, HASH_MULTIPLIER;
+        i32 => u32, 2654435769u32;
+        i64 => u64, 11400714819323198486u64;
+        i128 => u128, 210306068529402873165736369884012333108u128;
+        isize => usize, HASH_MULTIPLIER;
+    }
+
+    /// Randomizes the Fibonacci hash of a table so that keys chosen by an adversary can not
+    /// be aimed at a single home bucket. The key is xored and rotated by secret amounts and
+    /// then multiplied by a secret odd multiplier in place of the key's golden ratio
+    /// multiplier. Wider seeds are truncated to the width of the key.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct HashSeed {
+        /// Zero selects `Key::MULTIPLIER`; a seeded multiplier is always odd.
+        multiplier: u128,
+        xor: u128,
+        rotate: u32,
+    }
+
+    impl HashSeed {
+        /// The plain golden ratio hash used by tables that are not given a seed.
+        pub const FIXED: HashSeed = HashSeed {
+            multiplier: 0,
+            xor: 0,
+            rotate: 0,
+        };
+
+        /// Returns a seed drawn from the thread local random number generator.
+        pub fn random() -> Self {
+            Self::from_u64(rand::random::<u64>())
+        }
+
+        /// Derives a seed deterministically from `seed`, so runs can be reproduced.
+        pub fn from_u64(seed: u64) -> Self {
+            let mut state: u64 = seed;
+            let mut next = || -> u128 {
+                state = state.wrapping_add(0x9E3779B97F4A7C15);
+                let high: u64 = mix64(state);
+                state = state.wrapping_add(0x9E3779B97F4A7C15);
+                ((high as u128) << 64) | mix64(state) as u128
+            };
+            HashSeed {
+                multiplier: next() | 1,
+                xor: next(),
+                rotate: (next() % 128) as u32,
+            }
+        }
+
+        #[inline]
+        pub fn hash<K: Key>(&self, key: K, shift: usize) -> usize {
+            let multiplier: K = if self.multiplier == 0 {
+                K::MULTIPLIER
+            } else {
+                K::truncate(self.multiplier)
+            };
+            key.xor(K::truncate(self.xor))
+                .rotate_left(self.rotate % K::BITS)
+                .wrapping_mul(multiplier)
+                .high_word()
+                >> shift
+        }
+
+        /// Returns the seed a table switches to when it rehashes, as `next_seed` does for
+        /// plain seeds.
+        pub(crate) fn next(self) -> Self {
+            Self::from_u64(next_seed(self.xor as u64 ^ self.multiplier as u64))
+        }
+
+        /// Returns the seed as it applies to `usize` keys.
+        pub(crate) fn to_words(self) -> [usize; 3] {
+            [
+                self.multiplier as usize,
+                self.xor as usize,
+                (self.rotate % USIZE_BITS as u32) as usize,
+            ]
+        }
+
+        pub(crate) fn from_words(words: [usize; 3]) -> Option<Self> {
+            if words[2] >= USIZE_BITS || (words[0] != 0 && words[0] & 1 == 0) {
+                return None;
+            }
+            Some(HashSeed {
+                multiplier: words[0] as u128,
+                xor: words[1] as u128,
+                rotate: words[2] as u32,
+            })
+        }
+    }
+
+    impl Default for HashSeed {
+        fn default() -> Self {
+            Self::FIXED
+        }
+    }
+
+    /// Maps a key to a full word hash. Tables keep only the top bits of the hash as the
+    /// home bucket, so an implementation must spread the entropy of the key into its high
+    /// bits.
+    pub trait KeyHasher<K = usize>: Clone + Debug {
+        fn hash(&self, key: K) -> usize;
+
+        /// Switches to a new seed derived from the current one and returns true, or returns
+        /// false if the hasher has no seed. Tables call this when chains grow pathologically
+        /// long.
+        fn reseed(&mut self) -> bool {
+            false
+        }
+    }
+
+    /// Fibonacci hashing: multiplication by 2^BITS divided by the golden ratio, optionally
+    /// randomized by a `HashSeed`. This is the hasher tables use unless told otherwise.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct Fibonacci {
+        seed: HashSeed,
+    }
+
+    impl Fibonacci {
+        pub fn with_seed(seed: HashSeed) -> Self {
+            Fibonacci { seed }
+        }
+
+        #[inline]
+        pub fn seed(&self) -> HashSeed {
+            self.seed
+        }
+    }
+
+    impl<K: Key> KeyHasher<K> for Fibonacci {
+        #[inline]
+        fn hash(&self, key: K) -> usize {
+            self.seed.hash(key, 0)
+        }
+
+        fn reseed(&mut self) -> bool {
+            self.seed = self.seed.next();
+            true
+        }
+    }
+
+    /// The splitmix64 finalizer, a variant of the murmur3 `fmix64` step. Every input bit
+    /// affects every output bit, so it copes with keys that differ only in a few high or
+    /// low bits, at the cost of two multiplications per key. For 128-bit keys the upper half
+    /// is mixed with the seed first and the lower half is mixed into the result, so every
+    /// bit of the key passes through the seeded mix and no pair of keys collides under
+    /// every seed.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct SplitMix64 {
+        seed: u64,
+    }
+
+    impl SplitMix64 {
+        pub fn with_seed(seed: u64) -> Self {
+            SplitMix64 { seed }
+        }
+    }
+
+    #[inline]
+    pub(crate) fn mix64(mut z: u64) -> u64 {
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns the seed a hasher switches to when its table rehashes. It is derived from
+    /// the current seed rather than drawn at random, so a table built from a fixed seed
+    /// rehashes the same way on every run.
+    #[inline]
+    pub(crate) fn next_seed(seed: u64) -> u64 {
+        mix64(seed.wrapping_add(0x9E3779B97F4A7C15))
+    }
+
+    impl<K: Key> KeyHasher<K> for SplitMix64 {
+        #[inline]
+        fn hash(&self, key: K) -> usize {
+            let bits: u128 = key.to_bits();
+            let mut seeded: u64 = self.seed;
+            if K::BITS > 64 {
+                seeded = mix64((bits >> 64) as u64 ^ seeded);
+            }
+            (mix64(bits as u64 ^ seeded) >> (64 - USIZE_BITS)) as usize
+        }
+
+        fn reseed(&mut self) -> bool {
+            self.seed = next_seed(self.seed);
+            true
+        }
+    }
+
+    const MAX_KEY_BYTES: usize = 16;
+
+    /// Simple tabulation hashing: one table of random words per key byte, xored together.
+    /// It is 3-independent and needs no multiplication, but each key costs one table
+    /// lookup per byte.
+    #[derive(Clone)]
+    pub struct Tabulation {
+        seed: u64,
+        tables: Box<[[usize; 256]; MAX_KEY_BYTES]>,
+    }
+
+    impl Tabulation {
+        /// Returns tables filled from the thread local random number generator.
+        pub fn random() -> Self {
+            Self::from_u64(rand::random::<u64>())
+        }
+
+        /// Fills the tables deterministically from `seed`.
+        pub fn from_u64(seed: u64) -> Self {
+            let mut tables: Box<[[usize; 256]; MAX_KEY_BYTES]> =
+                Box::new([[0; 256]; MAX_KEY_BYTES]);
+            let mut state: u64 = seed;
+            for table in tables.iter_mut() {
+                for word in table.iter_mut() {
+                    state = state.wrapping_add(0x9E3779B97F4A7C15);
+                    *word = mix64(state) as usize;
+                }
+            }
+            Tabulation { seed, tables }
+        }
+    }
+
+    impl Default for Tabulation {
+        fn default() -> Self {
+            Self::from_u64(0)
+        }
+    }
+
+    impl Debug for Tabulation {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("Tabulation").finish_non_exhaustive()
+        }
+    }
+
+    impl<K: Key> KeyHasher<K> for Tabulation {
+        #[inline]
+        fn hash(&self, key: K) -> usize {
+            let bits: u128 = key.to_bits();
+            let mut h: usize = 0;
+            for (i, table) in self.tables.iter().take(K::BITS as usize / 8).enumerate() {
+                h ^= table[(bits >> (8 * i)) as u8 as usize];
+            }
+            h
+        }
+
+        fn reseed(&mut self) -> bool {
+            *self = Tabulation::from_u64(next_seed(self.seed));
+            true
+        }
+    }
+
+    /// Uses the key itself as the hash. Only suitable for keys that are already uniformly
+    /// distributed over the whole word, such as random identifiers or precomputed hashes,
+    /// because the home bucket is taken from the high bits of the key.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct Identity;
+
+    impl<K: Key> KeyHasher<K> for Identity {
+        #[inline]
+        fn hash(&self, key: K) -> usize {
+            key.high_word()
+        }
+    }
+
+    /// Chooses the buckets `put` tries, in order, when a key needs a bucket other than its
+    /// home. The sequence is computed one step at a time from the previous bucket, and it
+    /// must reach every bucket of a power of two table, because the search only ends at an
+    /// empty one.
+    pub trait ProbeStrategy: Copy + Debug + Default {
+        /// Returns the bucket after `bucket` in the sequence for a key with the full hash
+        /// `hash`, where `step` counts the buckets tried so far, starting at 1.
+        fn next(bucket: usize, hash: usize, step: usize, mask: usize) -> usize;
+    }
+
+    /// Tries the buckets after home one by one. Chains stay within a few cache lines, but
+    /// runs of occupied buckets grow and the searches that cross them get long.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct Linear;
+
+    impl ProbeStrategy for Linear {
+        #[inline]
+        fn next(bucket: usize, _hash: usize, _step: usize, mask: usize) -> usize {
+            (bucket + 1) & mask
+        }
+    }
+
+    /// Offsets 1, 3, 6, 10, ... from home, the triangular numbers, which visit every bucket
+    /// of a power of two table. The first probes are still close to home while clusters
+    /// are broken up quickly. This is the strategy tables use unless told otherwise.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct Triangular;
+
+    impl ProbeStrategy for Triangular {
+        #[inline]
+        fn next(bucket: usize, _hash: usize, step: usize, mask: usize) -> usize {
+            (bucket + step) & mask
+        }
+    }
+
+    /// Steps by a stride taken from the low bits of the hash, forced odd so the sequence
+    /// covers the table. Keys with the same home follow different sequences, which avoids
+    /// secondary clustering, but every probe is likely a cache miss.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct DoubleHashing;
+
+    impl ProbeStrategy for DoubleHashing {
+        #[inline]
+        fn next(bucket: usize, hash: usize, _step: usize, mask: usize) -> usize {
+            (bucket + (hash | 1)) & mask
+        }
+    }
+
+    /// A pseudo random walk: the linear congruential step `5 * bucket + c` with an odd `c`
+    /// from the hash, which has full period modulo any power of two. It spreads probes
+    /// over the whole table like double hashing, without a fixed stride.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct RandomWalk;
+
+    impl ProbeStrategy for RandomWalk {
+        #[inline]
+        fn next(bucket: usize, hash: usize, _step: usize, mask: usize) -> usize {
+            bucket.wrapping_mul(5).wrapping_add(hash | 1) & mask
+        }
+    }
+
+    /// Decides whether buckets keep the full hash of their key. Caching the hash costs a
+    /// word per bucket but lets `grow` and the home bucket checks in `put` skip rehashing
+    /// keys, and lets lookups reject most non-matching buckets before comparing keys.
+    pub trait HashCache: Copy + Debug + Default {
+        fn store(hash: usize) -> Self;
+
+        /// Returns the cached hash, or `None` when hashes are not cached.
+        fn load(&self) -> Option<usize>;
+
+        /// Returns false only if the bucket certainly holds a key with a different hash.
+        #[inline]
+        fn may_match(&self, hash: usize) -> bool {
+            self.load().is_none_or(|stored| stored == hash)
+        }
+    }
+
+    /// Buckets do not cache hashes. This is the default and adds nothing to the bucket.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct NoHash;
+
+    impl HashCache for NoHash {
+        #[inline]
+        fn store(_hash: usize) -> Self {
+            NoHash
+        }
+
+        #[inline]
+        fn load(&self) -> Option<usize> {
+            None
+        }
+    }
+
+    /// Buckets cache the full hash of their key, for keys or hashers that are expensive.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct StoredHash(usize);
+
+    impl HashCache for StoredHash {
+        #[inline]
+        fn store(hash: usize) -> Self {
+            StoredHash(hash)
+        }
+
+        #[inline]
+        fn load(&self) -> Option<usize> {
+            Some(self.0)
+        }
+    }
+
+    // Returns true if the load factor is less than or equal to 0.375.
     /*#[inline]
     fn should_shrink(count: usize, capacity: usize) -> bool {
         count <= (capacity >> 2) + (capacity >> 3)
     }*/
 
+    /// Returns keys whose default Fibonacci hashes are the first `count` even numbers, so
+    /// all of them share home bucket 0 at any realistic capacity.
+    #[cfg(test)]
+    pub(crate) fn one_home_keys(count: usize) -> Vec<usize> {
+        // The multiplier is even; its odd half is invertible modulo 2^64, and Newton's
+        // iteration doubles the correct low bits of the inverse every step.
+        let multiplier: usize = HASH_MULTIPLIER >> 1;
+        let mut inverse: usize = multiplier;
+        for _ in 0..6 {
+            inverse = inverse.wrapping_mul(2usize.wrapping_sub(multiplier.wrapping_mul(inverse)));
+        }
+        (0..count).map(|i| i.wrapping_mul(inverse)).collect()
+    }
+
     pub mod map {
 
+        use super::{
+            Fibonacci, HashCache, HashSeed, Key, KeyHasher, NoHash, ProbeStrategy, StoredHash,
+            TableKey, Triangular,
+        };
         use std::alloc::{self, Layout};
         use std::fmt::Debug;
+        use std::marker::PhantomData;
         use std::mem;
         use std::ptr::{addr_of_mut, null_mut};
+        use std::thread;
+
+        /// How many keys ahead of the one being resolved `get_batch` and `put_batch`
+        /// prefetch home buckets.
+        pub const BATCH: usize = 16;
 
         #[derive(Clone, Copy, Debug)]
-        struct Bucket<T>
+        pub(crate) struct Bucket<K, T, C>
         where
+            K: TableKey,
             T: Default + Debug + Copy + Clone,
+            C: HashCache,
         {
-            next: *mut Bucket<T>,
-            key: usize,
-            value: T,
+            pub(crate) next: *mut Bucket<K, T, C>,
+            pub(crate) key: K,
+            pub(crate) hash: C,
+            pub(crate) value: T,
         }
 
-        impl<T> Default for Bucket<T>
+        impl<K, T, C> Default for Bucket<K, T, C>
         where
+            K: TableKey,
             T: Default + Debug + Copy + Clone,
+            C: HashCache,
         {
             fn default() -> Self {
                 Self {
                     next: null_mut(),
-                    key: 0,
+                    key: K::default(),
+                    hash: C::default(),
                     value: T::default(),
                 }
             }
         }
 
         #[derive(Clone, Debug)]
-        pub struct HashTable<T>
+        pub struct HashTable<T, K = usize, H = Fibonacci, C = NoHash, P = Triangular>
         where
             T: Default + Copy + Clone + Debug,
+            K: TableKey,
+            H: KeyHasher<K>,
+            C: HashCache,
+            P: ProbeStrategy,
         {
             count: usize,
             shift: usize,
             mask: usize,
             capacity: usize,
-            ptr: *mut Bucket<T>,
+            displaced: usize,
+            longest: usize,
+            rehashes: usize,
+            rehashed: bool,
+            auto_rehash: bool,
+            incremental: bool,
+            huge_pages: bool,
+            grow_threads: usize,
+            /// Set by `set_grow_threads`, where the bounds that moving keys across threads
+            /// needs are known.
+            parallel_grow: Option<GrowFn<T, K, H, C, P>>,
+            /// While a resize is in progress, the chains of every old home below this index
+            /// have been moved into the new array.
+            migrated: usize,
+            old_capacity: usize,
+            old_ptr: *mut Bucket<K, T, C>,
+            hasher: H,
+            probe: PhantomData<P>,
+            ptr: *mut Bucket<K, T, C>,
         }
 
-        impl<T> Default for HashTable<T>
+        /// A `HashTable` whose buckets cache the full hash of their key. Growing and the
+        /// home bucket check in `put` read the cached hash instead of hashing the key again,
+        /// and lookups compare hashes before comparing keys.
+        pub type CachedHashTable<T, K = usize, H = Fibonacci> = HashTable<T, K, H, StoredHash>;
+
+        type GrowFn<T, K, H, C, P> = fn(&mut HashTable<T, K, H, C, P>);
+
+        impl<T, K, H, C, P> Default for HashTable<T, K, H, C, P>
         where
             T: Default + Copy + Clone + Debug,
+            K: TableKey,
+            H: KeyHasher<K> + Default,
+            C: HashCache,
+            P: ProbeStrategy,
         {
             fn default() -> Self {
                 Self {
@@ -83,35 +594,106 @@ pub mod hash {
                     shift: 0,
                     mask: 0,
                     capacity: 0,
+                    displaced: 0,
+                    longest: 0,
+                    rehashes: 0,
+                    rehashed: false,
+                    auto_rehash: true,
+                    incremental: false,
+                    huge_pages: false,
+                    grow_threads: 1,
+                    parallel_grow: None,
+                    migrated: 0,
+                    old_capacity: 0,
+                    old_ptr: null_mut(),
+                    hasher: H::default(),
+                    probe: PhantomData,
                     ptr: null_mut(),
                 }
             }
         }
 
-        impl<T> Drop for HashTable<T>
+        impl<T, K, H, C, P> Drop for HashTable<T, K, H, C, P>
         where
             T: Default + Copy + Clone + Debug,
+            K: TableKey,
+            H: KeyHasher<K>,
+            C: HashCache,
+            P: ProbeStrategy,
         {
             fn drop(&mut self) {
-                let layout = Layout::array::<Bucket<T>>(self.capacity).unwrap();
-                unsafe { alloc::dealloc(self.ptr as *mut u8, layout) };
+                unsafe {
+                    Self::free_buckets(self.ptr, self.capacity, self.huge_pages);
+                    if !self.old_ptr.is_null() {
+                        Self::free_buckets(self.old_ptr, self.old_capacity, self.huge_pages);
+                    }
+                }
+            }
+        }
+
+        impl<T, K, C, P> HashTable<T, K, Fibonacci, C, P>
+        where
+            T: Default + Copy + Clone + Debug,
+            K: Key,
+            C: HashCache,
+            P: ProbeStrategy,
+        {
+            pub fn with_capacity(initial_capacity: usize) -> Self {
+                Self::with_capacity_and_hasher(initial_capacity, Fibonacci::default())
+            }
+
+            /// Creates a table that hashes with `seed` instead of the fixed golden ratio
+            /// multiplier. Use `HashSeed::random()` for tables keyed by untrusted input.
+            pub fn with_capacity_and_seed(initial_capacity: usize, seed: HashSeed) -> Self {
+                Self::with_capacity_and_hasher(initial_capacity, Fibonacci::with_seed(seed))
+            }
+
+            #[inline]
+            pub fn seed(&self) -> HashSeed {
+                self.hasher.seed()
             }
         }
 
-        impl<T> HashTable<T>
+        impl<T, K, H, C, P> HashTable<T, K, H, C, P>
         where
             T: Default + Copy + Clone + Debug,
+            K: TableKey,
+            H: KeyHasher<K>,
+            C: HashCache,
+            P: ProbeStrategy,
         {
-            pub const BUCKET_SIZE: usize = std::mem::size_of::<Bucket<T>>();
+            pub const BUCKET_SIZE: usize = std::mem::size_of::<Bucket<K, T, C>>();
             pub const MIN_BITS: usize = 1;
             pub const MAX_BITS: usize =
                 super::USIZE_BITS - (usize::MAX / Self::BUCKET_SIZE).leading_zeros() as usize;
             pub const MIN_CAPACITY: usize = 1 << Self::MIN_BITS;
             pub const MAX_CAPACITY: usize = 1 << Self::MAX_BITS;
+            /// The fewest old buckets a parallel grow hands to one thread.
+            pub const MIN_PARTITION: usize = 1 << 12;
+            /// The number of old buckets an insert migrates while a resize is in progress.
+            /// Growing from 15/16 load leaves about 15/16 of the old capacity in inserts
+            /// before the new array fills up, so any rate above one finishes in time.
+            pub const MIGRATE_BUCKETS: usize = 4;
 
             #[inline]
-            fn hash(&self, key: usize) -> usize {
-                key.wrapping_mul(super::HASH_MULTIPLIER) >> self.shift
+            fn hash(&self, key: K) -> usize {
+                self.hasher.hash(key) >> self.shift
+            }
+
+            /// Returns true if an insert has built a chain longer than 2 log2(capacity) + 4.
+            /// With a uniformly random hash the longest chain grows roughly like
+            /// log(capacity) / log(log(capacity)), so this only trips on a bad key
+            /// distribution or a hasher under attack.
+            #[inline]
+            pub(crate) fn chains_too_long(&self) -> bool {
+                self.longest > 2 * self.capacity.trailing_zeros() as usize + 4
+            }
+
+            #[inline]
+            fn note_chain(&mut self, length: usize) {
+                if length > self.longest {
+                    self.longest = length;
+                }
             }
 
             /// Returns true if the load factor greater than or equal to 0.9375.
@@ -129,16 +711,172 @@ pub mod hash {
                 }
             }
 
-            pub fn with_capacity(initial_capacity: usize) -> Self {
+            /// Returns the number of keys stored in the table.
+            #[inline]
+            pub fn len(&self) -> usize {
+                self.count
+            }
+
+            #[inline]
+            pub fn is_empty(&self) -> bool {
+                self.count == 0
+            }
+
+            #[inline]
+            pub fn capacity(&self) -> usize {
+                self.capacity
+            }
+
+            /// Returns the number of bytes allocated for buckets, including the old array
+            /// while a resize is in progress.
+            #[inline]
+            pub fn allocated_bytes(&self) -> usize {
+                (self.capacity + self.old_capacity) * Self::BUCKET_SIZE
+            }
+
+            /// Turns incremental resizing on or off. When it is on, an insert that crosses
+            /// the load threshold only allocates the new bucket array; the old array stays
+            /// alongside it and every later insert moves the chains of a few old buckets
+            /// across, so no single insert pays for rehashing the whole table. Turning it off
+            /// finishes any resize in progress.
+            ///
+            /// Only inserts move chains. Lookups take `&self` and hand out references into
+            /// the buckets, which moving chains under them would invalidate. Until enough
+            /// inserts follow, lookups search both arrays and a miss pays for both; call
+            /// `finish_resize` before a phase that mostly reads.
+            pub fn set_incremental(&mut self, incremental: bool) {
+                self.incremental = incremental;
+                if !incremental {
+                    self.finish_resize();
+                }
+            }
+
+            #[inline]
+            pub fn is_incremental(&self) -> bool {
+                self.incremental
+            }
+
+            /// Maps the bucket array with huge pages, now and after every grow, when `huge`
+            /// is set, or takes it from the global allocator again when it is not. Arrays of
+            /// 2 MiB or more are mapped with `MAP_HUGETLB` if the system has reserved huge
+            /// pages, and otherwise with ordinary pages advised with `MADV_HUGEPAGE`, so the
+            /// kernel backs them with transparent huge pages where it can. Smaller arrays,
+            /// and every array on systems other than Linux, are allocated as usual.
+            ///
+            /// The array is replaced without moving any keys, so the table must be empty.
+            pub fn set_huge_pages(&mut self, huge: bool) {
+                assert!(self.is_empty(), "Table is not empty");
+                self.finish_resize();
+                if huge != self.huge_pages && self.capacity > 0 {
+                    unsafe {
+                        let ptr: *mut Bucket<K, T, C> =
+                            Self::allocate_buckets(self.capacity, huge, true);
+                        Self::free_buckets(self.ptr, self.capacity, self.huge_pages);
+                        self.ptr = ptr;
+                    }
+                }
+                self.huge_pages = huge;
+            }
+
+            #[inline]
+            pub fn uses_huge_pages(&self) -> bool {
+                self.huge_pages
+            }
+
+            /// Returns the number of threads that grow the table; see `set_grow_threads`.
+            #[inline]
+            pub fn grow_threads(&self) -> usize {
+                self.grow_threads
+            }
+
+            /// Returns true while an incremental resize is moving keys out of the old
+            /// bucket array.
+            #[inline]
+            pub fn is_resizing(&self) -> bool {
+                !self.old_ptr.is_null()
+            }
+
+            /// Moves every key left in the old bucket array of an incremental resize, so lookups
+            /// search one array again.
+            pub fn finish_resize(&mut self) {
+                if self.is_resizing() {
+                    self.migrate(self.old_capacity);
+                }
+            }
+
+            #[inline]
+            pub(crate) fn shift(&self) -> usize {
+                self.shift
+            }
+
+            /// Returns how many times an insert found its home bucket holding a member of
+            /// another chain and moved that member out, since the bucket array was last
+            /// allocated.
+            #[inline]
+            pub(crate) fn displaced(&self) -> usize {
+                self.displaced
+            }
+
+            /// Turns the automatic rehash of tables with pathologically long chains on, the
+            /// default, or off. With it off the table keeps its hasher and seed whatever the
+            /// chains look like, so it can be used to measure the hasher itself.
+            pub fn set_auto_rehash(&mut self, auto_rehash: bool) {
+                self.auto_rehash = auto_rehash;
+            }
+
+            #[inline]
+            pub fn auto_rehash(&self) -> bool {
+                self.auto_rehash
+            }
+
+            /// Returns how many times the table was rebuilt because an insert made a chain
+            /// pathologically long.
+            #[inline]
+            pub fn rehashes(&self) -> usize {
+                self.rehashes
+            }
+
+            #[inline]
+            pub fn hasher(&self) -> &H {
+                &self.hasher
+            }
+
+            /// Returns the occupied buckets of the array with their indices, in bucket order.
+            /// During an incremental resize this is only the new array. Empty buckets are
+            /// skipped, since a grow initializes nothing in them but `next`.
+            pub(crate) fn occupied(&self) -> impl Iterator<Item = (usize, &Bucket<K, T, C>)> {
+                (0..self.capacity).filter_map(move |index| unsafe {
+                    let bucket: *const Bucket<K, T, C> = self.ptr.add(index);
+                    (!(*bucket).next.is_null()).then(|| (index, &*bucket))
+                })
+            }
+
+            /// Returns the index in the array of the bucket `bucket` links to.
+            #[inline]
+            pub(crate) fn next_index(&self, bucket: &Bucket<K, T, C>) -> usize {
+                (bucket.next as usize - self.ptr as usize) / Self::BUCKET_SIZE
+            }
+
+            /// Returns an iterator over the occupied buckets in bucket order, followed by
+            /// those of the old array during an incremental resize.
+            pub fn iter(&self) -> Iter<'_, K, T, C> {
+                Iter {
+                    ptr: self.ptr,
+                    index: 0,
+                    capacity: self.capacity,
+                    old_ptr: self.old_ptr,
+                    old_capacity: self.old_capacity,
+                    marker: PhantomData,
+                }
+            }
+
+            pub fn with_capacity_and_hasher(initial_capacity: usize, hasher: H) -> Self {
                 let bits: usize = (super::USIZE_BITS - initial_capacity.leading_zeros() as usize)
                     .min(Self::MAX_BITS)
                     .max(Self::MIN_BITS);
                 let capacity: usize = 1 << bits;
-                let layout: Layout = Self::create_layout(capacity);
-                let ptr: *mut Bucket<T> = unsafe { alloc::alloc(layout) as *mut Bucket<T> };
-                if ptr.is_null() {
-                    alloc::handle_alloc_error(layout);
-                }
+                let ptr: *mut Bucket<K, T, C> =
+                    unsafe { Self::allocate_buckets(capacity, false, false) };
                 for count in 0..capacity {
                     unsafe { *ptr.add(count) = Bucket::default() };
                 }
@@ -147,52 +885,303 @@ pub mod hash {
                     shift: capacity.leading_zeros() as usize + 1,
                     mask: capacity - 1,
                     capacity,
+                    displaced: 0,
+                    longest: 0,
+                    rehashes: 0,
+                    rehashed: false,
+                    auto_rehash: true,
+                    incremental: false,
+                    huge_pages: false,
+                    grow_threads: 1,
+                    parallel_grow: None,
+                    migrated: 0,
+                    old_capacity: 0,
+                    old_ptr: null_mut(),
+                    hasher,
+                    probe: PhantomData,
                     ptr,
                 }
             }
 
             fn create_layout(capacity: usize) -> Layout {
                 assert!(mem::size_of::<T>() != 0, "Capacity overflow");
-                let layout: Layout = Layout::array::<Bucket<T>>(capacity).unwrap();
+                let layout: Layout = Layout::array::<Bucket<K, T, C>>(capacity).unwrap();
                 assert!(layout.size() < isize::MAX as usize, "Allocation too large");
                 layout
             }
 
+            /// Returns true if an array of `capacity` buckets is mapped with huge pages rather
+            /// than taken from the global allocator. Arrays smaller than one huge page never
+            /// are.
+            #[inline]
+            fn maps_huge_pages(capacity: usize, huge: bool) -> bool {
+                cfg!(target_os = "linux")
+                    && huge
+                    && Self::create_layout(capacity).size() >= crate::huge_pages::HUGE_PAGE
+            }
+
+            /// Allocates an array of `capacity` buckets, mapped with huge pages if `huge` is
+            /// set and the array is large enough. Mapped memory is always zeroed, which leaves
+            /// every `next` null; other memory only when `zeroed` is set.
+            unsafe fn allocate_buckets(
+                capacity: usize,
+                huge: bool,
+                zeroed: bool,
+            ) -> *mut Bucket<K, T, C> {
+                let layout: Layout = Self::create_layout(capacity);
+                let ptr: *mut u8 = if Self::maps_huge_pages(capacity, huge) {
+                    crate::huge_pages::map(layout.size())
+                } else if zeroed {
+                    alloc::alloc_zeroed(layout)
+                } else {
+                    alloc::alloc(layout)
+                };
+                if ptr.is_null() {
+                    alloc::handle_alloc_error(layout);
+                }
+                ptr as *mut Bucket<K, T, C>
+            }
+
+            /// Frees an array returned by `allocate_buckets` for the same `capacity` and
+            /// `huge`.
+            unsafe fn free_buckets(ptr: *mut Bucket<K, T, C>, capacity: usize, huge: bool) {
+                if ptr.is_null() {
+                    return;
+                }
+                let layout: Layout = Self::create_layout(capacity);
+                if Self::maps_huge_pages(capacity, huge) {
+                    crate::huge_pages::unmap(ptr as *mut u8, layout.size());
+                } else {
+                    alloc::dealloc(ptr as *mut u8, layout);
+                }
+            }
+
+            /// Returns the home bucket of the key in `bucket`, from its cached hash when
+            /// there is one.
+            #[inline]
+            unsafe fn home_of(&self, bucket: *const Bucket<K, T, C>) -> usize {
+                match (*bucket).hash.load() {
+                    Some(hash) => hash >> self.shift,
+                    None => self.hash((*bucket).key),
+                }
+            }
+
+            /// Returns the full hash of the key in `bucket`, from its cache when there is one.
+            #[inline]
+            unsafe fn full_hash(&self, bucket: *const Bucket<K, T, C>) -> usize {
+                match (*bucket).hash.load() {
+                    Some(hash) => hash,
+                    None => self.hasher.hash((*bucket).key),
+                }
+            }
+
+            #[inline]
+            pub fn get(&self, key: K) -> Option<&T> {
+                self.find(self.hasher.hash(key), key)
+            }
+
+            /// Looks up every key of `keys` and stores the results, in the same order, in
+            /// `results`. Before a chain is walked the home bucket of the key `BATCH`
+            /// positions ahead is prefetched, so up to `BATCH` cache misses are in flight
+            /// instead of one after another. Prefetching whole groups of `BATCH` before
+            /// walking their chains measured slower than this rolling window.
+            pub fn get_batch<'a>(&'a self, keys: &[K], results: &mut [Option<&'a T>]) {
+                assert_eq!(keys.len(), results.len(), "Batch lengths differ");
+                let mut hashes: [usize; BATCH] = [0; BATCH];
+                for (hash, key) in hashes.iter_mut().zip(keys) {
+                    *hash = self.hasher.hash(*key);
+                    self.prefetch(*hash);
+                }
+                for index in 0..keys.len() {
+                    let hash: usize = hashes[index % BATCH];
+                    if let Some(ahead) = keys.get(index + BATCH) {
+                        hashes[index % BATCH] = self.hasher.hash(*ahead);
+                        self.prefetch(hashes[index % BATCH]);
+                    }
+                    results[index] = self.find(hash, keys[index]);
+                }
+            }
+
+            /// Hints the CPU to start loading the home bucket of `hash` into the cache.
+            /// Only a resize in progress can leave a key in another array, and that array is
+            /// not prefetched.
             #[inline]
-            pub fn get(&self, key: usize) -> Option<&T> {
+            fn prefetch(&self, hash: usize) {
+                #[cfg(all(target_arch = "x86_64", target_feature = "sse"))]
                 unsafe {
-                    let h: usize = self.hash(key);
-                    let mut bucket: *mut Bucket<T> = self.ptr.add(h);
-                    let origin: *mut Bucket<T> = bucket;
-                    loop {
-                        if (*bucket).key == key {
-                            return Some(&(*bucket).value);
-                        }
-                        bucket = (*bucket).next;
-                        if bucket == origin {
-                            return None;
+                    use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+                    let home: *const Bucket<K, T, C> = self.ptr.wrapping_add(hash >> self.shift);
+                    _mm_prefetch::<_MM_HINT_T0>(home as *const i8);
+                }
+                #[cfg(not(all(target_arch = "x86_64", target_feature = "sse")))]
+                let _ = hash;
+            }
+
+            /// Returns the full hash the table's hasher gives `key`, as taken by the
+            /// `_with_hash` methods. The home bucket is the top bits of this word, so it stays
+            /// valid when the table grows, but not after an automatic rehash replaced the
+            /// hasher's seed; `insert_with_hash` turns automatic rehash off for that reason.
+            #[inline]
+            pub fn hash_key(&self, key: K) -> usize {
+                self.hasher.hash(key)
+            }
+
+            /// Looks up `key` using `hash`, which must be the value `hash_key` returns for it.
+            /// This lets callers that already hashed their keys upstream skip hashing again.
+            #[inline]
+            pub fn get_with_hash(&self, hash: usize, key: K) -> Option<&T> {
+                debug_assert_eq!(
+                    hash,
+                    self.hasher.hash(key),
+                    "Hash does not match the table's hasher"
+                );
+                self.find(hash, key)
+            }
+
+            #[inline]
+            fn find(&self, hash: usize, key: K) -> Option<&T> {
+                unsafe {
+                    let found: Option<&T> = Self::find_in(self.ptr, self.shift, hash, key);
+                    if found.is_none() && self.is_resizing() {
+                        let home: usize = hash >> (self.shift + 1);
+                        if home >= self.migrated {
+                            return Self::find_in(self.old_ptr, self.shift + 1, hash, key);
                         }
                     }
+                    found
+                }
+            }
+
+            /// Walks the chain of `hash` in the bucket array at `ptr`, whose home buckets are
+            /// the hash shifted right by `shift`.
+            #[inline]
+            unsafe fn find_in<'a>(
+                ptr: *mut Bucket<K, T, C>,
+                shift: usize,
+                hash: usize,
+                key: K,
+            ) -> Option<&'a T> {
+                let mut bucket: *mut Bucket<K, T, C> = ptr.add(hash >> shift);
+                let origin: *mut Bucket<K, T, C> = bucket;
+                if (*origin).next.is_null() {
+                    return None;
+                }
+                loop {
+                    if (*bucket).hash.may_match(hash) && (*bucket).key == key {
+                        return Some(&(*bucket).value);
+                    }
+                    bucket = (*bucket).next;
+                    if bucket == origin {
+                        return None;
+                    }
                 }
             }
 
             fn grow(&mut self) {
+                match self.parallel_grow {
+                    Some(grow) if self.capacity >= 2 * Self::MIN_PARTITION => grow(self),
+                    _ => self.rebuild(2 * self.capacity, false),
+                }
+            }
+
+            /// Starts an incremental resize: allocates an array of twice the capacity and keeps
+            /// the current one as the old array, to be emptied by `migrate`.
+            fn start_resize(&mut self) {
+                self.finish_resize();
+                let new_cap: usize = 2 * self.capacity;
                 unsafe {
-                    let old_ptr: *mut Bucket<T> = self.ptr;
-                    let old_capacity: usize = self.capacity;
-                    let old_layout: Layout = Self::create_layout(old_capacity);
+                    // Zeroed memory leaves every `next` null without writing to the array,
+                    // so large arrays are mapped in page by page as inserts reach them.
+                    let new_ptr: *mut Bucket<K, T, C> =
+                        Self::allocate_buckets(new_cap, self.huge_pages, true);
+                    self.old_ptr = self.ptr;
+                    self.old_capacity = self.capacity;
+                    self.migrated = 0;
+                    self.ptr = new_ptr;
+                }
+                self.shift = new_cap.leading_zeros() as usize + 1;
+                self.mask = new_cap - 1;
+                self.capacity = new_cap;
+                self.displaced = 0;
+                self.longest = 0;
+                self.rehashed = false;
+            }
 
-                    let new_cap: usize = 2 * old_capacity;
-                    let new_layout: Layout = Self::create_layout(new_cap);
-                    let new_ptr: *mut Bucket<T> = alloc::alloc(new_layout) as *mut Bucket<T>;
+            /// Moves the chains of the next `buckets` old home buckets into the new array,
+            /// and frees the old array once it is empty.
+            fn migrate(&mut self, buckets: usize) {
+                let end: usize = self.migrated.saturating_add(buckets).min(self.old_capacity);
+                while self.migrated < end {
+                    unsafe { self.migrate_chain(self.migrated) };
+                    self.migrated += 1;
+                }
+                if self.migrated == self.old_capacity {
+                    unsafe { Self::free_buckets(self.old_ptr, self.old_capacity, self.huge_pages) };
+                    self.old_ptr = null_mut();
+                    self.old_capacity = 0;
+                    self.migrated = 0;
+                }
+            }
 
-                    if new_ptr.is_null() {
-                        alloc::handle_alloc_error(new_layout);
+            /// Moves the chain of the old home bucket `home`, if it has one, into the new
+            /// array. A chain always starts at its home bucket, so a bucket holding a member
+            /// of another chain is left for that chain's home.
+            unsafe fn migrate_chain(&mut self, home: usize) {
+                let origin: *mut Bucket<K, T, C> = self.old_ptr.add(home);
+                if (*origin).next.is_null() || self.full_hash(origin) >> (self.shift + 1) != home {
+                    return;
+                }
+                let mut bucket: *mut Bucket<K, T, C> = origin;
+                loop {
+                    let next: *mut Bucket<K, T, C> = (*bucket).next;
+                    let hash: usize = self.full_hash(bucket);
+                    self.emplace(hash, (*bucket).key, (*bucket).value);
+                    (*bucket).next = null_mut();
+                    if next == origin {
+                        break;
                     }
+                    bucket = next;
+                }
+            }
+
+            /// Rebuilds the table after an insert made a chain pathologically long: with a
+            /// new seed if the hasher has one, otherwise by growing once the table is at
+            /// least half full. This happens at most once per capacity, so keys that no
+            /// seed can separate do not make every insert rebuild the table.
+            fn rehash_long_chains(&mut self) {
+                // The old array of a resize in progress is filed under the current seed.
+                self.finish_resize();
+                if self.hasher.reseed() {
+                    self.rebuild(self.capacity, true);
+                } else if self.count >= self.capacity / 2 {
+                    self.grow();
+                } else {
+                    return;
+                }
+                self.rehashed = true;
+                self.rehashes += 1;
+            }
+
+            /// Moves every key into a new array of `new_cap` buckets. Cached hashes are
+            /// reused unless `rehash` is set, which is needed after the hasher changed.
+            ///
+            /// Keys are reinserted with `emplace` in bucket order; the homes of old bucket `i`
+            /// are new buckets `2i` and `2i + 1`, so bucket order fills the new array nearly
+            /// front to back.
+            fn rebuild(&mut self, new_cap: usize, rehash: bool) {
+                self.finish_resize();
+                unsafe {
+                    let old_ptr: *mut Bucket<K, T, C> = self.ptr;
+                    let old_capacity: usize = self.capacity;
+                    let new_ptr: *mut Bucket<K, T, C> =
+                        Self::allocate_buckets(new_cap, self.huge_pages, false);
 
                     self.shift = new_cap.leading_zeros() as usize + 1;
                     self.mask = new_cap - 1;
                     self.capacity = new_cap;
+                    self.displaced = 0;
+                    self.longest = 0;
+                    self.rehashed &= new_cap == old_capacity;
                     self.ptr = new_ptr;
 
                     for count in 0..new_cap {
@@ -200,108 +1189,199 @@ pub mod hash {
                     }
 
                     for count in 0..old_capacity {
-                        let b: *mut Bucket<T> = old_ptr.add(count);
+                        let b: *mut Bucket<K, T, C> = old_ptr.add(count);
                         if !(*b).next.is_null() {
-                            self.emplace((*b).key, (*b).value);
+                            let hash: usize = match (*b).hash.load() {
+                                Some(hash) if !rehash => hash,
+                                _ => self.hasher.hash((*b).key),
+                            };
+                            self.emplace(hash, (*b).key, (*b).value);
                         }
                     }
 
-                    alloc::dealloc(old_ptr as *mut u8, old_layout);
+                    Self::free_buckets(old_ptr, old_capacity, self.huge_pages);
+                }
+            }
+
+            #[inline]
+            pub fn put(&mut self, key: K, value: T) -> Option<T> {
+                self.insert(self.hasher.hash(key), key, value)
+            }
+
+            /// Inserts `key` using `hash`, which must be the value `hash_key` returns for it.
+            /// A wrong hash would file the key under another home bucket and break the
+            /// chains every later insert relies on, so it is checked in debug builds.
+            ///
+            /// Callers keep hashes computed upstream, which a new seed would make stale, so
+            /// this turns automatic rehash off for good; see `set_auto_rehash`.
+            #[inline]
+            pub fn insert_with_hash(&mut self, hash: usize, key: K, value: T) -> Option<T> {
+                self.auto_rehash = false;
+                self.insert_with_fresh_hash(hash, key, value)
+            }
+
+            /// Like `insert_with_hash`, for callers that computed `hash` with `hash_key` just
+            /// before and keep no other hashes, so an automatic rehash can stay on.
+            #[inline]
+            pub(crate) fn insert_with_fresh_hash(
+                &mut self,
+                hash: usize,
+                key: K,
+                value: T,
+            ) -> Option<T> {
+                debug_assert_eq!(
+                    hash,
+                    self.hasher.hash(key),
+                    "Hash does not match the table's hasher"
+                );
+                self.insert(hash, key, value)
+            }
+
+            /// Puts every entry of `entries` and stores what each put returned, in the same
+            /// order, in `results`, prefetching ahead like `get_batch`. A grow only wastes the
+            /// prefetches in flight, but a rehash replaces the hasher's seed, so the keys
+            /// already hashed are hashed again.
+            pub fn put_batch(&mut self, entries: &[(K, T)], results: &mut [Option<T>]) {
+                assert_eq!(entries.len(), results.len(), "Batch lengths differ");
+                let mut hashes: [usize; BATCH] = [0; BATCH];
+                for (hash, (key, _)) in hashes.iter_mut().zip(entries) {
+                    *hash = self.hasher.hash(*key);
+                    self.prefetch(*hash);
+                }
+                let mut rehashes: usize = self.rehashes;
+                for index in 0..entries.len() {
+                    if self.rehashes != rehashes {
+                        rehashes = self.rehashes;
+                        for ahead in index..(index + BATCH).min(entries.len()) {
+                            hashes[ahead % BATCH] = self.hasher.hash(entries[ahead].0);
+                        }
+                    }
+                    let hash: usize = hashes[index % BATCH];
+                    if let Some((ahead, _)) = entries.get(index + BATCH) {
+                        hashes[index % BATCH] = self.hasher.hash(*ahead);
+                        self.prefetch(hashes[index % BATCH]);
+                    }
+                    let (key, value): (K, T) = entries[index];
+                    results[index] = self.insert(hash, key, value);
                 }
             }
 
             #[inline]
-            pub fn put(&mut self, key: usize, value: T) -> Option<T> {
+            fn insert(&mut self, hash: usize, key: K, value: T) -> Option<T> {
                 unsafe {
                     if self.should_grow() {
-                        self.grow();
+                        if self.incremental {
+                            self.start_resize();
+                        } else {
+                            self.grow();
+                        }
                     }
-                    match self.emplace(key, value) {
+                    if self.is_resizing() {
+                        // The key must leave the old array before it can be updated or
+                        // added in the new one.
+                        let home: usize = hash >> (self.shift + 1);
+                        if home >= self.migrated {
+                            self.migrate_chain(home);
+                        }
+                        self.migrate(Self::MIGRATE_BUCKETS);
+                    }
+                    match self.emplace(hash, key, value) {
                         Some(b) => Some(b),
                         None => {
                             self.count += 1;
+                            if self.auto_rehash && !self.rehashed && self.chains_too_long() {
+                                self.rehash_long_chains();
+                            }
                             None
                         }
                     }
                 }
             }
 
+            /// Returns the first empty bucket of the probe sequence that starts at `home`.
+            /// The table never fills up completely, so the search always ends.
+            #[inline]
+            unsafe fn free_bucket(&self, home: usize, hash: usize) -> *mut Bucket<K, T, C> {
+                let mut h: usize = home;
+                for step in 1usize.. {
+                    h = P::next(h, hash, step, self.mask);
+                    if (*self.ptr.add(h)).next.is_null() {
+                        break;
+                    }
+                }
+                self.ptr.add(h)
+            }
+
+            /// Inserts `key`, whose full hash is `hash`, without checking the load factor.
             #[inline]
-            unsafe fn emplace(&mut self, key: usize, value: T) -> Option<T> {
-                let mut h: usize = self.hash(key);
-                let origin: *mut Bucket<T> = self.ptr.wrapping_add(h);
-                let mut next: *mut Bucket<T> = (*origin).next;
-                let mut curr: *mut Bucket<T>;
+            unsafe fn emplace(&mut self, hash: usize, key: K, value: T) -> Option<T> {
+                let h: usize = hash >> self.shift;
+                let origin: *mut Bucket<K, T, C> = self.ptr.wrapping_add(h);
+                let mut next: *mut Bucket<K, T, C> = (*origin).next;
+                let mut curr: *mut Bucket<K, T, C>;
 
                 if next.is_null() {
                     *origin = Bucket {
                         next: origin,
                         key,
+                        hash: C::store(hash),
                         value,
                     };
                     None
                 } else if next == origin {
-                    if (*origin).key == key {
+                    if (*origin).hash.may_match(hash) && (*origin).key == key {
                         Some(addr_of_mut!((*origin).value).replace(value))
                     } else {
-                        for x in 1usize.. {
-                            h = (h + x) & self.mask;
-                            next = self.ptr.wrapping_add(h);
-                            if (*next).next.is_null() {
-                                *next = Bucket {
-                                    next: origin,
-                                    key,
-                                    value,
-                                };
-                                (*origin).next = next;
-                                break;
-                            }
-                        }
+                        next = self.free_bucket(h, hash);
+                        *next = Bucket {
+                            next: origin,
+                            key,
+                            hash: C::store(hash),
+                            value,
+                        };
+                        (*origin).next = next;
+                        self.note_chain(2);
                         None
                     }
-                } else if h == self.hash((*origin).key) {
+                } else if h == self.home_of(origin) {
                     curr = origin;
+                    let mut length: usize = 1;
                     loop {
-                        if (*curr).key == key {
+                        if (*curr).hash.may_match(hash) && (*curr).key == key {
                             return Some(addr_of_mut!((*curr).value).replace(value));
                         }
                         if next == origin {
-                            for probe in 1usize.. {
-                                h = (h + probe) & self.mask;
-                                next = self.ptr.wrapping_add(h);
-                                if (*next).next.is_null() {
-                                    *next = Bucket {
-                                        next: origin,
-                                        key,
-                                        value,
-                                    };
-                                    (*curr).next = next;
-                                    return None;
-                                }
-                            }
+                            next = self.free_bucket(h, hash);
+                            *next = Bucket {
+                                next: origin,
+                                key,
+                                hash: C::store(hash),
+                                value,
+                            };
+                            (*curr).next = next;
+                            self.note_chain(length + 1);
+                            return None;
                         }
                         curr = next;
                         next = (*curr).next;
+                        length += 1;
                     }
                 } else {
                     loop {
                         curr = next;
                         next = (*curr).next;
                         if next == origin {
-                            for probe in 1usize.. {
-                                h = (h + probe) & self.mask;
-                                next = self.ptr.wrapping_add(h);
-                                if (*next).next.is_null() {
-                                    *next = *origin;
-                                    (*curr).next = next;
-                                    *origin = Bucket {
-                                        next: origin,
-                                        key,
-                                        value,
-                                    };
-                                    return None;
-                                }
-                            }
+                            next = self.free_bucket(h, hash);
+                            *next = *origin;
+                            (*curr).next = next;
+                            self.displaced += 1;
+                            *origin = Bucket {
+                                next: origin,
+                                key,
+                                hash: C::store(hash),
+                                value,
+                            };
+                            return None;
                         }
                     }
                 }
@@ -316,7 +1396,7 @@ pub mod hash {
                     self.capacity,
                     self.load_factor()
                 );
-                /*let buckets = self.ptr.add(1) as *mut Bucket<T>;
+                /*let buckets = self.ptr.add(1) as *mut Bucket<K, T, C>;
                 let mut i: usize = 0;
                 while i < self.capacity {
                     println!("{:?}", buckets.add(i).read());
@@ -325,5 +1405,399 @@ pub mod hash {
                 println!();*/
             }
         }
+
+        impl<T, K, H, C, P> HashTable<T, K, H, C, P>
+        where
+            T: Default + Copy + Clone + Debug + Send,
+            K: TableKey + Send,
+            H: KeyHasher<K> + Send,
+            C: HashCache + Send,
+            P: ProbeStrategy,
+        {
+            /// Makes every later grow of a large table run on `threads` threads; 1, the
+            /// default, grows on the calling thread. Incremental resizing, when turned on,
+            /// takes precedence.
+            ///
+            /// The old bucket array is split into one range per thread. The keys of old
+            /// bucket `i` belong in new buckets `2i` and `2i + 1` or close after them, so each
+            /// thread fills the new buckets of its range alone. A key whose home or free
+            /// bucket lies outside its thread's range is left behind, and those keys are
+            /// inserted on the calling thread afterwards, in bucket order, so the new array
+            /// does not depend on how the threads were scheduled. The table holds the same
+            /// entries as after a serial grow, though chains may be in a different order.
+            pub fn set_grow_threads(&mut self, threads: usize) {
+                assert!(threads >= 1, "At least one thread is needed");
+                self.grow_threads = threads;
+                self.parallel_grow = if threads > 1 {
+                    Some(Self::grow_parallel)
+                } else {
+                    None
+                };
+            }
+
+            fn grow_parallel(&mut self) {
+                self.finish_resize();
+                let old_ptr: *mut Bucket<K, T, C> = self.ptr;
+                let old_capacity: usize = self.capacity;
+                let new_cap: usize = 2 * old_capacity;
+                let new_ptr: *mut Bucket<K, T, C> =
+                    unsafe { Self::allocate_buckets(new_cap, self.huge_pages, false) };
+
+                self.shift = new_cap.leading_zeros() as usize + 1;
+                self.mask = new_cap - 1;
+                self.capacity = new_cap;
+                self.displaced = 0;
+                self.longest = 0;
+                self.rehashed = false;
+                self.ptr = new_ptr;
+
+                let threads: usize = self
+                    .grow_threads
+                    .min(old_capacity / Self::MIN_PARTITION)
+                    .max(1);
+                let chunk: usize = old_capacity.div_ceil(threads);
+                let longest: Option<usize> = thread::scope(|scope| {
+                    let mut workers = Vec::with_capacity(threads);
+                    for lo in (0..old_capacity).step_by(chunk) {
+                        let partition: Partition<K, T, C, H, P> = Partition {
+                            old: old_ptr,
+                            new: new_ptr,
+                            lo,
+                            hi: (lo + chunk).min(old_capacity),
+                            shift: self.shift,
+                            mask: self.mask,
+                            hasher: self.hasher.clone(),
+                            probe: PhantomData,
+                        };
+                        workers.push(scope.spawn(move || unsafe { partition.run() }));
+                    }
+                    workers
+                        .into_iter()
+                        .map(|worker| worker.join().expect("Grow thread panicked"))
+                        .max()
+                });
+                self.longest = longest.unwrap_or(0);
+
+                unsafe {
+                    for count in 0..old_capacity {
+                        let b: *mut Bucket<K, T, C> = old_ptr.add(count);
+                        if !(*b).next.is_null() {
+                            let hash: usize = self.full_hash(b);
+                            self.emplace(hash, (*b).key, (*b).value);
+                        }
+                    }
+                    Self::free_buckets(old_ptr, old_capacity, self.huge_pages);
+                }
+            }
+        }
+
+        /// One thread's share of a parallel grow: the old buckets `lo..hi` and the new
+        /// buckets `2 * lo..2 * hi` their keys mostly belong in. Threads never touch each
+        /// other's buckets.
+        struct Partition<K, T, C, H, P>
+        where
+            K: TableKey,
+            T: Default + Copy + Clone + Debug,
+            C: HashCache,
+        {
+            old: *mut Bucket<K, T, C>,
+            new: *mut Bucket<K, T, C>,
+            lo: usize,
+            hi: usize,
+            shift: usize,
+            mask: usize,
+            hasher: H,
+            probe: PhantomData<P>,
+        }
+
+        unsafe impl<K, T, C, H, P> Send for Partition<K, T, C, H, P>
+        where
+            K: TableKey + Send,
+            T: Default + Copy + Clone + Debug + Send,
+            C: HashCache + Send,
+            H: Send,
+        {
+        }
+
+        impl<K, T, C, H, P> Partition<K, T, C, H, P>
+        where
+            K: TableKey,
+            T: Default + Copy + Clone + Debug,
+            C: HashCache,
+            H: KeyHasher<K>,
+            P: ProbeStrategy,
+        {
+            #[inline]
+            unsafe fn full_hash(&self, bucket: *const Bucket<K, T, C>) -> usize {
+                match (*bucket).hash.load() {
+                    Some(hash) => hash,
+                    None => self.hasher.hash((*bucket).key),
+                }
+            }
+
+            /// Clears the partition's new buckets and moves into them every key it can place
+            /// there, clearing its old bucket; the keys still in the old buckets are left for
+            /// the calling thread. Homes are filled first, so no key has to be moved out of a
+            /// home, and the other keys are linked in right after the key at their home.
+            /// Returns the length of the longest chain built.
+            unsafe fn run(&self) -> usize {
+                let start: usize = 2 * self.lo;
+                let end: usize = 2 * self.hi;
+                for count in start..end {
+                    (*self.new.add(count)).next = null_mut();
+                }
+
+                // The length of the chain at each home, saturating far above any chain that
+                // `chains_too_long` lets stand.
+                let mut lengths: Vec<u8> = vec![0; end - start];
+                let mut longest: u8 = 0;
+                for count in self.lo..self.hi {
+                    let b: *mut Bucket<K, T, C> = self.old.add(count);
+                    if (*b).next.is_null() {
+                        continue;
+                    }
+                    let hash: usize = self.full_hash(b);
+                    let h: usize = hash >> self.shift;
+                    if !(start..end).contains(&h) {
+                        continue;
+                    }
+                    let home: *mut Bucket<K, T, C> = self.new.add(h);
+                    if (*home).next.is_null() {
+                        *home = Bucket {
+                            next: home,
+                            key: (*b).key,
+                            hash: C::store(hash),
+                            value: (*b).value,
+                        };
+                        (*b).next = null_mut();
+                        lengths[h - start] = 1;
+                        longest = 1;
+                    }
+                }
+
+                'keys: for count in self.lo..self.hi {
+                    let b: *mut Bucket<K, T, C> = self.old.add(count);
+                    if (*b).next.is_null() {
+                        continue;
+                    }
+                    let hash: usize = self.full_hash(b);
+                    let h: usize = hash >> self.shift;
+                    if !(start..end).contains(&h) {
+                        continue;
+                    }
+                    let home: *mut Bucket<K, T, C> = self.new.add(h);
+                    if (*home).next.is_null() || self.full_hash(home) >> self.shift != h {
+                        continue;
+                    }
+                    let mut free: usize = h;
+                    for step in 1usize.. {
+                        free = P::next(free, hash, step, self.mask);
+                        if !(start..end).contains(&free) {
+                            continue 'keys;
+                        }
+                        if (*self.new.add(free)).next.is_null() {
+                            break;
+                        }
+                    }
+                    let next: *mut Bucket<K, T, C> = self.new.add(free);
+                    *next = Bucket {
+                        next: (*home).next,
+                        key: (*b).key,
+                        hash: C::store(hash),
+                        value: (*b).value,
+                    };
+                    (*home).next = next;
+                    (*b).next = null_mut();
+
+                    let length: &mut u8 = &mut lengths[h - start];
+                    *length = length.saturating_add(1);
+                    longest = longest.max(*length);
+                }
+                longest as usize
+            }
+        }
+
+        pub struct Iter<'a, K, T, C = NoHash>
+        where
+            K: TableKey,
+            T: Default + Copy + Clone + Debug,
+            C: HashCache,
+        {
+            ptr: *const Bucket<K, T, C>,
+            index: usize,
+            capacity: usize,
+            old_ptr: *const Bucket<K, T, C>,
+            old_capacity: usize,
+            marker: PhantomData<&'a T>,
+        }
+
+        impl<'a, K, T, C> Iterator for Iter<'a, K, T, C>
+        where
+            K: TableKey,
+            T: Default + Copy + Clone + Debug,
+            C: HashCache,
+        {
+            type Item = (K, &'a T);
+
+            fn next(&mut self) -> Option<Self::Item> {
+                loop {
+                    while self.index < self.capacity {
+                        let b: *const Bucket<K, T, C> = unsafe { self.ptr.add(self.index) };
+                        self.index += 1;
+                        unsafe {
+                            if !(*b).next.is_null() {
+                                return Some(((*b).key, &(*b).value));
+                            }
+                        }
+                    }
+                    if self.old_capacity == 0 {
+                        return None;
+                    }
+                    self.ptr = self.old_ptr;
+                    self.index = 0;
+                    self.capacity = self.old_capacity;
+                    self.old_capacity = 0;
+                }
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<T, K, H, C, P> serde::Serialize for HashTable<T, K, H, C, P>
+        where
+            T: Default + Copy + Clone + Debug + serde::Serialize,
+            K: TableKey + serde::Serialize,
+            H: KeyHasher<K>,
+            C: HashCache,
+            P: ProbeStrategy,
+        {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeMap;
+                // `Iter` has no exact size hint, and formats such as bincode need the length
+                // up front.
+                let mut map = serializer.serialize_map(Some(self.len()))?;
+                for (key, value) in self.iter() {
+                    map.serialize_entry(&key, value)?;
+                }
+                map.end()
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de, T, K, H, C, P> serde::Deserialize<'de> for HashTable<T, K, H, C, P>
+        where
+            T: Default + Copy + Clone + Debug + serde::Deserialize<'de>,
+            K: TableKey + serde::Deserialize<'de>,
+            H: KeyHasher<K> + Default,
+            C: HashCache,
+            P: ProbeStrategy,
+        {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer.deserialize_map(crate::serde_impl::TableVisitor::new(
+                    |capacity| Self::with_capacity_and_hasher(capacity, H::default()),
+                    |table: &mut Self, key, value| {
+                        table.put(key, value);
+                    },
+                ))
+            }
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::super::{one_home_keys, Key};
+            use super::HashTable;
+
+            /// Puts `keys` into a table that grows several times, then checks every key and
+            /// `misses`. Narrow keys only spread over the homes if `high_word` left aligns
+            /// them, which the rehash count checks.
+            fn puts_and_gets<K: Key>(keys: &[K], misses: &[K]) {
+                let mut table: HashTable<usize, K> = HashTable::with_capacity(16);
+                for (value, key) in keys.iter().enumerate() {
+                    assert_eq!(table.put(*key, value), None);
+                }
+                assert_eq!(table.put(keys[0], usize::MAX), Some(0));
+                assert_eq!(table.len(), keys.len());
+                assert!(table.capacity() > 16 * 64);
+                assert_eq!(table.rehashes(), 0);
+                assert_eq!(table.get(keys[0]), Some(&usize::MAX));
+                for (value, key) in keys.iter().enumerate().skip(1) {
+                    assert_eq!(table.get(*key), Some(&value));
+                }
+                for key in misses {
+                    assert_eq!(table.get(*key), None);
+                }
+            }
+
+            #[test]
+            fn u32_keys() {
+                let keys: Vec<u32> = (0..5000).map(|i| i * 7 + 1).collect();
+                puts_and_gets(&keys, &[0, 2, u32::MAX]);
+            }
+
+            #[test]
+            fn negative_i64_keys() {
+                let keys: Vec<i64> = (-2500..2500).collect();
+                puts_and_gets(&keys, &[i64::MIN, -2501, 2500, i64::MAX]);
+            }
+
+            #[test]
+            fn u128_keys_differing_in_the_upper_half() {
+                let keys: Vec<u128> = (0..5000).map(|i| (i << 64) | 42).collect();
+                puts_and_gets(&keys, &[41, 43, 5000 << 64 | 42, (1 << 64) | 41]);
+            }
+
+            #[test]
+            fn get_stops_at_an_empty_home() {
+                let mut table: HashTable<usize> = HashTable::with_capacity(16);
+                table.put(1, 5);
+                // Key 0 lives in bucket 0, which is empty and holds the default key 0.
+                assert_eq!(table.get(0), None);
+                assert_eq!(table.get(1), Some(&5));
+            }
+
+            #[test]
+            fn put_rehashes_long_chains() {
+                let mut table: HashTable<usize> = HashTable::with_capacity(16);
+                for key in one_home_keys(3000) {
+                    table.put(key, key);
+                }
+                assert_eq!(table.rehashes(), 1);
+                assert!(one_home_keys(3000)
+                    .into_iter()
+                    .all(|key| table.get(key) == Some(&key)));
+            }
+
+            #[test]
+            fn rehash_is_reproducible() {
+                let tables: Vec<HashTable<usize>> = (0..2)
+                    .map(|_| {
+                        let mut table: HashTable<usize> = HashTable::with_capacity(16);
+                        for key in one_home_keys(3000) {
+                            table.put(key, key);
+                        }
+                        table
+                    })
+                    .collect();
+                assert_eq!(tables[0].rehashes(), 1);
+                assert_eq!(tables[0].seed(), tables[1].seed());
+                assert!(tables[0].iter().eq(tables[1].iter()));
+            }
+
+            #[test]
+            fn caller_hashes_stay_valid() {
+                let mut table: HashTable<usize> = HashTable::with_capacity(16);
+                let keys: Vec<(usize, usize)> = one_home_keys(3000)
+                    .into_iter()
+                    .map(|key| (key, table.hash_key(key)))
+                    .collect();
+                for (key, hash) in keys.iter().copied() {
+                    table.insert_with_hash(hash, key, key);
+                }
+                assert_eq!(table.rehashes(), 0);
+                assert!(!table.auto_rehash());
+                assert!(keys
+                    .iter()
+                    .all(|(key, hash)| table.get_with_hash(*hash, *key) == Some(key)));
+            }
+        }
     }
 }
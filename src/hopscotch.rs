@@ -0,0 +1,267 @@
+use crate::hash3::hash::{Fibonacci, HashSeed, Key, KeyHasher, TableKey, USIZE_BITS};
+use std::fmt::Debug;
+use std::mem;
+
+/// The size of a neighborhood: every key is within this many buckets of its home.
+const NEIGHBORHOOD: usize = 64;
+
+/// How far past its home an insert looks for a free bucket before growing the table.
+/// Near 15/16 load the nearest free bucket is often hundreds of buckets away.
+const MAX_DISTANCE: usize = 4096;
+
+#[derive(Clone, Copy, Debug, Default)]
+struct HopBucket<K, T>
+where
+    K: TableKey,
+    T: Default + Copy + Clone + Debug,
+{
+    /// Bit `i` is set when bucket `home + i` holds a key whose home is this bucket.
+    hop: u64,
+    full: bool,
+    key: K,
+    value: T,
+}
+
+/// Hopscotch hashing, a middle ground between the chains of `HashTable` and plain linear
+/// probing.
+///
+/// Every key is kept within the 64 buckets that start at its home bucket, and the home
+/// bucket records in a bitmap which of them hold its keys. A lookup reads the bitmap and
+/// checks only those buckets, which are mostly in the first cache lines after the home
+/// however full the table is. With 32 buckets the neighborhoods fill up at a load of
+/// about 0.85; 64 buckets keep most tables growing at the 15/16 load `HashTable` uses.
+///
+/// An insert takes the nearest free bucket; while that bucket is too far from home, a key
+/// between the two that may move there is hopped forward, so the free bucket moves back
+/// towards home. An insert that finds no such key grows the table.
+#[derive(Clone, Debug)]
+pub struct HopscotchTable<T, K = usize, H = Fibonacci>
+where
+    T: Default + Copy + Clone + Debug,
+    K: TableKey,
+    H: KeyHasher<K>,
+{
+    count: usize,
+    shift: usize,
+    mask: usize,
+    hasher: H,
+    buckets: Vec<HopBucket<K, T>>,
+}
+
+impl<T, K> HopscotchTable<T, K, Fibonacci>
+where
+    T: Default + Copy + Clone + Debug,
+    K: Key,
+{
+    pub fn with_capacity(initial_capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(initial_capacity, Fibonacci::default())
+    }
+
+    pub fn with_capacity_and_seed(initial_capacity: usize, seed: HashSeed) -> Self {
+        Self::with_capacity_and_hasher(initial_capacity, Fibonacci::with_seed(seed))
+    }
+}
+
+impl<T, K, H> HopscotchTable<T, K, H>
+where
+    T: Default + Copy + Clone + Debug,
+    K: TableKey,
+    H: KeyHasher<K>,
+{
+    pub const BUCKET_SIZE: usize = mem::size_of::<HopBucket<K, T>>();
+    /// A table is never smaller than one neighborhood, so a neighborhood never wraps onto
+    /// itself.
+    pub const MIN_BITS: usize = 6;
+
+    pub fn with_capacity_and_hasher(initial_capacity: usize, hasher: H) -> Self {
+        let bits: usize =
+            (USIZE_BITS - initial_capacity.leading_zeros() as usize).max(Self::MIN_BITS);
+        Self::allocate(bits, hasher)
+    }
+
+    fn allocate(bits: usize, hasher: H) -> Self {
+        assert!(bits < USIZE_BITS, "Allocation too large");
+        let capacity: usize = 1 << bits;
+        HopscotchTable {
+            count: 0,
+            shift: USIZE_BITS - bits,
+            mask: capacity - 1,
+            hasher,
+            buckets: vec![HopBucket::default(); capacity],
+        }
+    }
+
+    #[inline]
+    fn hash(&self, key: K) -> usize {
+        self.hasher.hash(key) >> self.shift
+    }
+
+    /// Returns true if the load factor is greater than or equal to 0.9375, the same
+    /// threshold as `HashTable`.
+    #[inline]
+    fn should_grow(&self) -> bool {
+        self.count >= (self.buckets.len() - (self.buckets.len() >> 4))
+    }
+
+    #[inline]
+    pub fn load_factor(&self) -> f64 {
+        self.count as f64 / self.buckets.len() as f64
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// Returns the number of bytes allocated for buckets.
+    #[inline]
+    pub fn allocated_bytes(&self) -> usize {
+        self.buckets.len() * Self::BUCKET_SIZE
+    }
+
+    #[inline]
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Returns the bucket holding `key`, searching the neighborhood of `home`.
+    #[inline]
+    fn find(&self, home: usize, key: K) -> Option<usize> {
+        let mut hop: u64 = self.buckets[home].hop;
+        while hop != 0 {
+            let index: usize = (home + hop.trailing_zeros() as usize) & self.mask;
+            if self.buckets[index].key == key {
+                return Some(index);
+            }
+            hop &= hop - 1;
+        }
+        None
+    }
+
+    #[inline]
+    pub fn get(&self, key: K) -> Option<&T> {
+        self.find(self.hash(key), key)
+            .map(|index| &self.buckets[index].value)
+    }
+
+    pub fn put(&mut self, key: K, value: T) -> Option<T> {
+        let home: usize = self.hash(key);
+        if let Some(index) = self.find(home, key) {
+            return Some(mem::replace(&mut self.buckets[index].value, value));
+        }
+        if self.should_grow() {
+            self.grow();
+        }
+        while !self.insert(key, value) {
+            self.grow();
+        }
+        self.count += 1;
+        None
+    }
+
+    /// Places a key that is not in the table, or returns false if there is no free bucket
+    /// that can be brought into its neighborhood. The count is left to the caller.
+    fn insert(&mut self, key: K, value: T) -> bool {
+        let home: usize = self.hash(key);
+        let mut distance: usize = 0;
+        while self.buckets[(home + distance) & self.mask].full {
+            distance += 1;
+            if distance == MAX_DISTANCE {
+                return false;
+            }
+        }
+        let mut free: usize = (home + distance) & self.mask;
+
+        while distance >= NEIGHBORHOOD {
+            free = match self.hop_back(free) {
+                Some(index) => index,
+                None => return false,
+            };
+            distance = free.wrapping_sub(home) & self.mask;
+        }
+
+        self.buckets[free] = HopBucket {
+            hop: self.buckets[free].hop,
+            full: true,
+            key,
+            value,
+        };
+        self.buckets[home].hop |= 1 << distance;
+        true
+    }
+
+    /// Moves a key from the buckets just before the free bucket `free` into it, and
+    /// returns the bucket the key left. The earliest home is tried first, so the free
+    /// bucket moves back as far as possible.
+    fn hop_back(&mut self, free: usize) -> Option<usize> {
+        for back in (1..NEIGHBORHOOD).rev() {
+            let home: usize = free.wrapping_sub(back) & self.mask;
+            // Only keys before `free` in this neighborhood can move there.
+            let movable: u64 = self.buckets[home].hop & ((1 << back) - 1);
+            if movable != 0 {
+                let offset: usize = movable.trailing_zeros() as usize;
+                let index: usize = (home + offset) & self.mask;
+                self.buckets[free].full = true;
+                self.buckets[free].key = self.buckets[index].key;
+                self.buckets[free].value = self.buckets[index].value;
+                self.buckets[index].full = false;
+                self.buckets[home].hop ^= (1 << offset) | (1 << back);
+                return Some(index);
+            }
+        }
+        None
+    }
+
+    pub fn remove(&mut self, key: K) -> Option<T> {
+        let home: usize = self.hash(key);
+        let index: usize = self.find(home, key)?;
+        self.buckets[index].full = false;
+        self.buckets[home].hop &= !(1 << (index.wrapping_sub(home) & self.mask));
+        self.count -= 1;
+        Some(self.buckets[index].value)
+    }
+
+    /// Doubles the table, or more if the hasher sends too many keys to one neighborhood
+    /// for them to fit at twice the size.
+    fn grow(&mut self) {
+        let entries: Vec<(K, T)> = self.iter().map(|(key, value)| (key, *value)).collect();
+        let mut bits: usize = USIZE_BITS - self.shift + 1;
+        loop {
+            *self = Self::allocate(bits, self.hasher.clone());
+            if entries.iter().all(|(key, value)| self.insert(*key, *value)) {
+                self.count = entries.len();
+                return;
+            }
+            bits += 1;
+        }
+    }
+
+    /// Returns an iterator over the full buckets in bucket order.
+    pub fn iter(&self) -> impl Iterator<Item = (K, &T)> + '_ {
+        self.buckets
+            .iter()
+            .filter(|bucket| bucket.full)
+            .map(|bucket| (bucket.key, &bucket.value))
+    }
+
+    pub fn print(&self) {
+        println!(
+            "count {}, shift {}, mask {}, cap {}, load {}",
+            self.count,
+            self.shift,
+            self.mask,
+            self.buckets.len(),
+            self.load_factor()
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! Anonymous memory mappings backed by 2 MiB huge pages, for bucket arrays large enough
+//! that TLB misses dominate lookups.
+//!
+//! A mapping first asks for pages from the reserved hugetlbfs pool with `MAP_HUGETLB`.
+//! Most systems reserve none, so the usual result is a plain mapping that is advised with
+//! `MADV_HUGEPAGE` and backed by transparent huge pages wherever the kernel can find
+//! them. Either way the memory starts zeroed. Other systems get no huge pages: there
+//! `map` always fails, and callers check `cfg!(target_os = "linux")` before using it.
+
+use std::ptr::null_mut;
+
+/// The huge page size assumed for rounding lengths; the default on x86-64 and AArch64.
+pub const HUGE_PAGE: usize = 2 << 20;
+
+#[inline]
+fn mapped_len(size: usize) -> usize {
+    size.next_multiple_of(HUGE_PAGE)
+}
+
+/// Maps at least `size` zeroed bytes at a huge page boundary, or returns null if the kernel
+/// refuses both kinds of mapping.
+#[cfg(target_os = "linux")]
+pub unsafe fn map(size: usize) -> *mut u8 {
+    let len: usize = mapped_len(size);
+    let prot: libc::c_int = libc::PROT_READ | libc::PROT_WRITE;
+    let flags: libc::c_int = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+    let ptr: *mut libc::c_void =
+        libc::mmap(null_mut(), len, prot, flags | libc::MAP_HUGETLB, -1, 0);
+    if ptr != libc::MAP_FAILED {
+        return ptr as *mut u8;
+    }
+    // A plain mapping is only page aligned, and transparent huge pages can only back
+    // aligned extents, so map one huge page more and trim the slack on both sides.
+    let ptr: *mut libc::c_void = libc::mmap(null_mut(), len + HUGE_PAGE, prot, flags, -1, 0);
+    if ptr == libc::MAP_FAILED {
+        return null_mut();
+    }
+    let head: usize = (ptr as usize).next_multiple_of(HUGE_PAGE) - ptr as usize;
+    let start: *mut libc::c_void = (ptr as *mut u8).add(head) as *mut libc::c_void;
+    if head > 0 {
+        libc::munmap(ptr, head);
+    }
+    if head < HUGE_PAGE {
+        libc::munmap(
+            (start as *mut u8).add(len) as *mut libc::c_void,
+            HUGE_PAGE - head,
+        );
+    }
+    // Transparent huge pages may be turned off, which leaves ordinary pages behind.
+    libc::madvise(start, len, libc::MADV_HUGEPAGE);
+    start as *mut u8
+}
+
+#[cfg(not(target_os = "linux"))]
+pub unsafe fn map(_size: usize) -> *mut u8 {
+    null_mut()
+}
+
+/// Unmaps memory returned by `map` for the same `size`.
+#[cfg(target_os = "linux")]
+pub unsafe fn unmap(ptr: *mut u8, size: usize) {
+    libc::munmap(ptr as *mut libc::c_void, mapped_len(size));
+}
+
+#[cfg(not(target_os = "linux"))]
+pub unsafe fn unmap(_ptr: *mut u8, _size: usize) {}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::{map, unmap, HUGE_PAGE};
+
+    #[test]
+    fn mappings_are_aligned_and_zeroed() {
+        for size in [1, HUGE_PAGE, 3 * HUGE_PAGE + 1] {
+            unsafe {
+                let ptr: *mut u8 = map(size);
+                assert!(!ptr.is_null());
+                assert_eq!(ptr as usize % HUGE_PAGE, 0);
+                let bytes: &mut [u8] = std::slice::from_raw_parts_mut(ptr, size);
+                assert!(bytes.iter().all(|byte| *byte == 0));
+                bytes.fill(1);
+                unmap(ptr, size);
+            }
+        }
+    }
+}
@@ -1,2 +1,19 @@
+pub mod analysis;
+pub mod archive;
+pub mod cellar;
+pub mod chained;
+pub mod const_table;
+pub mod cuckoo;
+pub mod frozen;
 pub mod hash2;
-pub mod hash3;
\ No newline at end of file
+pub mod hash3;
+pub mod hopscotch;
+mod huge_pages;
+pub mod robin_hood;
+#[cfg(unix)]
+pub mod shm;
+pub mod str_table;
+pub mod swiss;
+
+#[cfg(feature = "serde")]
+mod serde_impl;
@@ -1,52 +1,682 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rusty_buckets::analysis;
+use rusty_buckets::cellar::CellarHashTable;
+use rusty_buckets::chained::ChainedTable;
+use rusty_buckets::cuckoo::CuckooTable;
+use rusty_buckets::hash3::hash::map::{CachedHashTable, HashTable};
+use rusty_buckets::hash3::hash::{
+    DoubleHashing, Fibonacci, HashSeed, Identity, KeyHasher, Linear, NoHash, RandomWalk,
+    SplitMix64, Tabulation, Triangular,
+};
+use rusty_buckets::hopscotch::HopscotchTable;
+use rusty_buckets::robin_hood::RobinHoodTable;
+use rusty_buckets::swiss::SwissTable;
+use std::collections::HashMap;
+use std::io::Read;
+use std::rc::Rc;
+use std::thread;
+use std::time::Instant;
 
-use rusty_buckets::hash3::hash::map::HashTable;
+const CAPACITY: usize = (1 << 21) - 1;
+const SAMPLE_SIZE: usize = (CAPACITY as f64 * 0.93) as usize;
 
+/// Builds the keys for a workload: `random` (the default), `sequential` or `high-bits`,
+/// where keys differ only in their upper bits.
+fn samples(workload: &str) -> Rc<[usize]> {
+    (0..SAMPLE_SIZE)
+        .map(|i| match workload {
+            "sequential" => i + 100,
+            "high-bits" => i << (usize::BITS - 24),
+            _ => rand::random::<usize>(),
+        })
+        .collect()
+}
 
-fn main() {
+fn bench<H>(name: &str, hasher: H, samples: Rc<[usize]>, huge_pages: bool)
+where
+    H: KeyHasher + 'static,
+{
+    let bench_result = benchmarking::measure_function(move |measurer| {
+        let mut h: HashTable<usize, usize, H> =
+            HashTable::with_capacity_and_hasher(CAPACITY, hasher.clone());
+        h.set_huge_pages(huge_pages);
 
-    const CAPACITY: usize = (1 << 21) - 1;
-    const SAMPLE_SIZE: usize = (CAPACITY as f64 * 0.93) as usize;
+        measurer.measure(|| {
+            for key in samples.iter() {
+                h.put(*key, *key);
+            }
+        });
+
+        for key in samples.iter() {
+            match h.get(*key) {
+                Some(_) => (),
+                None => panic!("Failed to get key {}", key),
+            }
+        }
+    })
+    .unwrap();
+
+    println!(
+        "{:<12} {:>8.2} ns/put",
+        name,
+        bench_result.elapsed().as_nanos() as f64 / SAMPLE_SIZE as f64
+    );
+}
+
+fn run_benchmarks(workload: &str, huge_pages: bool) {
+    let samples: Rc<[usize]> = samples(workload);
 
     benchmarking::warm_up();
 
-    let bench_result = benchmarking::measure_function(|measurer| {
+    println!(
+        "workload {}, {} keys, huge pages {}",
+        workload, SAMPLE_SIZE, huge_pages
+    );
+    bench(
+        "fibonacci",
+        Fibonacci::default(),
+        samples.clone(),
+        huge_pages,
+    );
+    bench(
+        "splitmix64",
+        SplitMix64::default(),
+        samples.clone(),
+        huge_pages,
+    );
+    bench(
+        "tabulation",
+        Tabulation::random(),
+        samples.clone(),
+        huge_pages,
+    );
+    // Sequential keys leave the high bits clear, which would put every key in one chain.
+    if workload != "sequential" {
+        bench("identity", Identity, samples, huge_pages);
+    }
+}
+
+/// The operations the conformance checks and the table comparison run on every table
+/// design, with `usize` keys and values.
+trait Table {
+    const REMOVES: bool = false;
 
-        let mut samples: Box<[usize; SAMPLE_SIZE]> = Box::new([0; SAMPLE_SIZE]);
-        for i in 0..SAMPLE_SIZE {
-            samples[i] = rand::random::<usize>();
-            //samples[i] = i+100;
+    fn with_capacity(capacity: usize) -> Self;
+
+    fn len(&self) -> usize;
+
+    fn get(&self, key: usize) -> Option<&usize>;
+
+    fn put(&mut self, key: usize, value: usize) -> Option<usize>;
+
+    fn remove(&mut self, _key: usize) -> Option<usize> {
+        unreachable!("Table does not support remove")
+    }
+
+    fn allocated_bytes(&self) -> usize;
+}
+
+macro_rules! impl_table {
+    ($table:ty) => {
+        impl_table!($table, {});
+    };
+    ($table:ty, removes) => {
+        impl_table!($table, {
+            const REMOVES: bool = true;
+
+            fn remove(&mut self, key: usize) -> Option<usize> {
+                <$table>::remove(self, key)
+            }
+        });
+    };
+    ($table:ty, { $($removes:tt)* }) => {
+        impl Table for $table {
+            $($removes)*
+
+            fn with_capacity(capacity: usize) -> Self {
+                <$table>::with_capacity(capacity)
+            }
+
+            fn len(&self) -> usize {
+                <$table>::len(self)
+            }
+
+            fn get(&self, key: usize) -> Option<&usize> {
+                <$table>::get(self, key)
+            }
+
+            fn put(&mut self, key: usize, value: usize) -> Option<usize> {
+                <$table>::put(self, key, value)
+            }
+
+            fn allocated_bytes(&self) -> usize {
+                <$table>::allocated_bytes(self)
+            }
         }
+    };
+}
+
+impl_table!(HashTable<usize>);
+impl_table!(HashTable<usize, usize, Fibonacci, NoHash, Linear>);
+impl_table!(HashTable<usize, usize, Fibonacci, NoHash, DoubleHashing>);
+impl_table!(HashTable<usize, usize, Fibonacci, NoHash, RandomWalk>);
+impl_table!(CachedHashTable<usize>);
+impl_table!(RobinHoodTable<usize>, removes);
+impl_table!(CellarHashTable<usize>);
+impl_table!(SwissTable<usize>);
+impl_table!(CuckooTable<usize>, removes);
+impl_table!(HopscotchTable<usize>, removes);
+impl_table!(ChainedTable<usize>, removes);
+
+/// Defines a `Table` wrapping a `HashTable<usize>` with a setter called on creation.
+macro_rules! configured_table {
+    ($name:ident, $setter:ident($arg:expr)) => {
+        struct $name(HashTable<usize>);
+
+        impl Table for $name {
+            fn with_capacity(capacity: usize) -> Self {
+                let mut table: HashTable<usize> = HashTable::with_capacity(capacity);
+                table.$setter($arg);
+                $name(table)
+            }
+
+            fn len(&self) -> usize {
+                self.0.len()
+            }
 
-        //samples.sort();
+            fn get(&self, key: usize) -> Option<&usize> {
+                self.0.get(key)
+            }
 
-        //let samples: [usize; SAMPLE_SIZE] = [17892297645547504311, 3887224688403108501, 751982014720306921, 6826701797237034623, 1345369401946882797, 13935654535271135208, 4145353771167126259, 15786910114348623016];
+            fn put(&mut self, key: usize, value: usize) -> Option<usize> {
+                self.0.put(key, value)
+            }
 
-        let mut h: HashTable<usize> = HashTable::<usize>::with_capacity(CAPACITY);
+            fn allocated_bytes(&self) -> usize {
+                self.0.allocated_bytes()
+            }
+        }
+    };
+}
 
-        h.print();
+configured_table!(IncrementalHashTable, set_incremental(true));
+configured_table!(ParallelGrowHashTable, set_grow_threads(4));
+configured_table!(HugePageHashTable, set_huge_pages(true));
+configured_table!(FixedSeedHashTable, set_auto_rehash(false));
 
+fn bench_table<Tb>(name: &str, samples: &Rc<[usize]>, misses: &Rc<[usize]>)
+where
+    Tb: Table + 'static,
+{
+    let keys: Rc<[usize]> = samples.clone();
+    let insert = benchmarking::measure_function(move |measurer| {
+        let mut table: Tb = Tb::with_capacity(CAPACITY);
         measurer.measure(|| {
-            for i in 0..SAMPLE_SIZE {
-                h.put(samples[i], samples[i]);
+            for key in keys.iter() {
+                table.put(*key, *key);
             }
         });
+    })
+    .unwrap();
 
-        h.print();
+    let mut table: Tb = Tb::with_capacity(CAPACITY);
+    for key in samples.iter() {
+        table.put(*key, *key);
+    }
+    let bytes_per_key: f64 = table.allocated_bytes() as f64 / table.len() as f64;
+    let table: Rc<Tb> = Rc::new(table);
 
-        //measurer.measure(|| {
-            for i in 0..SAMPLE_SIZE {
-                match h.get(samples[i]){
-                    Some(_) => (),
-                    None => panic!("Failed to get key {}", samples[i]),
-                }
+    let lookup = |keys: Rc<[usize]>| {
+        let table: Rc<Tb> = table.clone();
+        benchmarking::measure_function(move |measurer| {
+            measurer.measure(|| keys.iter().filter(|key| table.get(**key).is_some()).count());
+        })
+        .unwrap()
+    };
+    let hit = lookup(samples.clone());
+    let miss = lookup(misses.clone());
+
+    let per_key = |result: benchmarking::MeasureResult| {
+        result.elapsed().as_nanos() as f64 / SAMPLE_SIZE as f64
+    };
+    println!(
+        "{:<12} {:>10.2} {:>10.2} {:>10.2} {:>10.2}",
+        name,
+        per_key(insert),
+        per_key(hit),
+        per_key(miss),
+        bytes_per_key
+    );
+}
+
+/// `tables [workload]`
+///
+/// Compares the table designs on one workload: nanoseconds per insert, per successful and
+/// per unsuccessful lookup, and bytes allocated per key once every key is inserted.
+fn compare_tables(workload: &str) {
+    let samples: Rc<[usize]> = samples(workload);
+    // Odd random keys: the high-bits workload has only even keys and the others are
+    // unlikely to contain any of them.
+    let misses: Rc<[usize]> = (0..SAMPLE_SIZE)
+        .map(|_| rand::random::<usize>() | 1)
+        .collect();
+
+    benchmarking::warm_up();
+
+    println!("workload {}, {} keys", workload, SAMPLE_SIZE);
+    println!(
+        "{:<12} {:>10} {:>10} {:>10} {:>10}",
+        "table", "ns/insert", "ns/hit", "ns/miss", "bytes/key"
+    );
+    bench_table::<HashTable<usize>>("hash3", &samples, &misses);
+    bench_table::<HashTable<usize, usize, Fibonacci, NoHash, Linear>>("linear", &samples, &misses);
+    bench_table::<HashTable<usize, usize, Fibonacci, NoHash, DoubleHashing>>(
+        "double", &samples, &misses,
+    );
+    bench_table::<HashTable<usize, usize, Fibonacci, NoHash, RandomWalk>>(
+        "random-walk",
+        &samples,
+        &misses,
+    );
+    bench_table::<RobinHoodTable<usize>>("robin_hood", &samples, &misses);
+    bench_table::<CellarHashTable<usize>>("cellar", &samples, &misses);
+    bench_table::<SwissTable<usize>>("swiss", &samples, &misses);
+    bench_table::<CuckooTable<usize>>("cuckoo", &samples, &misses);
+    bench_table::<HopscotchTable<usize>>("hopscotch", &samples, &misses);
+    bench_table::<ChainedTable<usize>>("chained", &samples, &misses);
+}
+
+fn check<Tb: Table>(name: &str, operations: usize, seed: u64) {
+    let mut rng: StdRng = StdRng::seed_from_u64(seed);
+    let mut table: Tb = Tb::with_capacity(16);
+    let mut expected: HashMap<usize, usize> = HashMap::new();
+    // Keys are drawn from half as many values as there are operations, so puts often
+    // replace and lookups often hit. Every other key has only high bits set.
+    let range: usize = (operations / 2).max(1);
+    for operation in 0..operations {
+        let mut key: usize = rng.gen_range(0..range);
+        if key & 1 == 1 {
+            key = key.reverse_bits();
+        }
+        let choice: u32 = rng.gen_range(0..10);
+        let (actual, wanted): (Option<usize>, Option<usize>) = if choice < 5 {
+            (table.put(key, operation), expected.insert(key, operation))
+        } else if choice < 8 || !Tb::REMOVES {
+            (table.get(key).copied(), expected.get(&key).copied())
+        } else {
+            (table.remove(key), expected.remove(&key))
+        };
+        assert_eq!(
+            actual, wanted,
+            "{}: operation {} on key {} differs",
+            name, operation, key
+        );
+    }
+    assert_eq!(table.len(), expected.len(), "{}: length differs", name);
+    for (key, value) in expected.iter() {
+        assert_eq!(
+            table.get(*key),
+            Some(value),
+            "{}: key {} differs",
+            name,
+            key
+        );
+    }
+    println!(
+        "{:<12} ok, {} operations, {} keys",
+        name,
+        operations,
+        table.len()
+    );
+}
+
+/// `conformance [operations] [seed]`
+///
+/// Runs the same random mix of puts, gets and, where supported, removes against every
+/// table design and `std::collections::HashMap`, and panics at the first result that
+/// differs.
+fn conformance(args: &[String]) {
+    const USAGE: &str = "usage: conformance [operations] [seed]";
+    let operations: usize = args
+        .first()
+        .map_or(1_000_000, |arg| arg.parse().expect(USAGE));
+    let seed: u64 = args.get(1).map_or(0, |arg| arg.parse().expect(USAGE));
+
+    check::<HashTable<usize>>("hash3", operations, seed);
+    check::<HashTable<usize, usize, Fibonacci, NoHash, Linear>>("linear", operations, seed);
+    check::<HashTable<usize, usize, Fibonacci, NoHash, DoubleHashing>>("double", operations, seed);
+    check::<HashTable<usize, usize, Fibonacci, NoHash, RandomWalk>>(
+        "random-walk",
+        operations,
+        seed,
+    );
+    check::<CachedHashTable<usize>>("cached", operations, seed);
+    check::<FixedSeedHashTable>("fixed-seed", operations, seed);
+    check::<RobinHoodTable<usize>>("robin_hood", operations, seed);
+    check::<CellarHashTable<usize>>("cellar", operations, seed);
+    check::<SwissTable<usize>>("swiss", operations, seed);
+    check::<CuckooTable<usize>>("cuckoo", operations, seed);
+    check::<HopscotchTable<usize>>("hopscotch", operations, seed);
+    check::<ChainedTable<usize>>("chained", operations, seed);
+    check::<IncrementalHashTable>("incremental", operations, seed);
+    check::<ParallelGrowHashTable>("parallel", operations, seed);
+    check::<HugePageHashTable>("huge-pages", operations, seed);
+}
+
+/// Inserts the samples into a table that starts small, so it grows many times, and prints
+/// percentiles of the time single puts took.
+fn put_latency<Tb: Table>(name: &str, samples: &[usize]) {
+    let mut table: Tb = Tb::with_capacity(16);
+    let mut times: Vec<u64> = samples
+        .iter()
+        .map(|key| {
+            let start: Instant = Instant::now();
+            table.put(*key, *key);
+            start.elapsed().as_nanos() as u64
+        })
+        .collect();
+    times.sort_unstable();
+    let percentile = |p: f64| times[((times.len() - 1) as f64 * p) as usize];
+    println!(
+        "{:<12} {:>10} {:>10} {:>10} {:>10}",
+        name,
+        percentile(0.5),
+        percentile(0.99),
+        percentile(0.999),
+        times[times.len() - 1]
+    );
+}
+
+/// Returns the shortest time, in milliseconds, that the put which doubles a table of
+/// `capacity` buckets took over `runs` runs.
+fn grow_time<F>(capacity: usize, runs: usize, mut grow: F) -> f64
+where
+    F: FnMut(&[usize]) -> f64,
+{
+    let keys: Vec<usize> = (0..capacity - capacity / 16)
+        .map(|_| rand::random::<usize>())
+        .collect();
+    (0..runs).map(|_| grow(&keys)).fold(f64::MAX, f64::min)
+}
+
+/// `grow`
+///
+/// Times the put that doubles a full table, for tables that cache hashes and tables that
+/// do not, and for a table that grows on one thread per CPU, or two on a single CPU.
+fn compare_grow() {
+    let threads: usize = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .max(2);
+    println!(
+        "{:<12} {:>10} {:>10} {:>10} {:>10}",
+        "capacity",
+        "hash3 ms",
+        "cached ms",
+        format!("{} thr ms", threads),
+        "ns/key"
+    );
+    for bits in [12, 16, 20, 22] {
+        let capacity: usize = 1 << bits;
+        let runs: usize = if bits < 20 { 50 } else { 5 };
+        let plain: f64 = grow_time(capacity, runs, |keys| {
+            let mut table: HashTable<usize> = HashTable::with_capacity(capacity - 1);
+            for key in keys {
+                table.put(*key, *key);
+            }
+            let start: Instant = Instant::now();
+            table.put(0, 0);
+            start.elapsed().as_secs_f64() * 1e3
+        });
+        let cached: f64 = grow_time(capacity, runs, |keys| {
+            let mut table: CachedHashTable<usize> = CachedHashTable::with_capacity(capacity - 1);
+            for key in keys {
+                table.put(*key, *key);
+            }
+            let start: Instant = Instant::now();
+            table.put(0, 0);
+            start.elapsed().as_secs_f64() * 1e3
+        });
+        let parallel: f64 = grow_time(capacity, runs, |keys| {
+            let mut table: HashTable<usize> = HashTable::with_capacity(capacity - 1);
+            table.set_grow_threads(threads);
+            for key in keys {
+                table.put(*key, *key);
             }
-        //});
+            let start: Instant = Instant::now();
+            table.put(0, 0);
+            start.elapsed().as_secs_f64() * 1e3
+        });
+        println!(
+            "{:<12} {:>10.3} {:>10.3} {:>10.3} {:>10.2}",
+            capacity,
+            plain,
+            cached,
+            parallel,
+            plain * 1e6 / (capacity - capacity / 16) as f64
+        );
+    }
+}
+
+/// `latency [workload]`
+///
+/// Compares the latency of single puts with eager and incremental resizing.
+fn latency(workload: &str) {
+    let samples: Rc<[usize]> = samples(workload);
+    println!("workload {}, {} keys", workload, SAMPLE_SIZE);
+    println!(
+        "{:<12} {:>10} {:>10} {:>10} {:>10}",
+        "table", "p50 ns", "p99 ns", "p99.9 ns", "max ns"
+    );
+    put_latency::<HashTable<usize>>("hash3", &samples);
+    put_latency::<IncrementalHashTable>("incremental", &samples);
+}
 
-        h.print();
+/// `batch [workload]`
+///
+/// Compares single puts and gets with `put_batch` and `get_batch`, in nanoseconds per key.
+fn compare_batch(workload: &str, huge_pages: bool) {
+    let samples: Rc<[usize]> = samples(workload);
+    let entries: Rc<[(usize, usize)]> = samples.iter().map(|key| (*key, *key)).collect();
 
-    }).unwrap();
+    benchmarking::warm_up();
 
-    println!("Time elapsed {}", bench_result.elapsed().as_nanos() as f64 / SAMPLE_SIZE as f64);
-    
+    let keys: Rc<[usize]> = samples.clone();
+    let put = benchmarking::measure_function(move |measurer| {
+        let mut table: HashTable<usize> = HashTable::with_capacity(CAPACITY);
+        table.set_huge_pages(huge_pages);
+        measurer.measure(|| {
+            for key in keys.iter() {
+                table.put(*key, *key);
+            }
+        });
+    })
+    .unwrap();
+    let put_batch = benchmarking::measure_function(move |measurer| {
+        let mut table: HashTable<usize> = HashTable::with_capacity(CAPACITY);
+        table.set_huge_pages(huge_pages);
+        let mut results: Vec<Option<usize>> = vec![None; entries.len()];
+        measurer.measure(|| table.put_batch(&entries, &mut results));
+    })
+    .unwrap();
+
+    let mut table: HashTable<usize> = HashTable::with_capacity(CAPACITY);
+    table.set_huge_pages(huge_pages);
+    for key in samples.iter() {
+        table.put(*key, *key);
+    }
+    let table: Rc<HashTable<usize>> = Rc::new(table);
+
+    let (keys, lookups): (Rc<[usize]>, Rc<HashTable<usize>>) = (samples.clone(), table.clone());
+    let get = benchmarking::measure_function(move |measurer| {
+        measurer.measure(|| {
+            keys.iter()
+                .filter(|key| lookups.get(**key).is_some())
+                .count()
+        });
+    })
+    .unwrap();
+    let get_batch = benchmarking::measure_function(move |measurer| {
+        let mut results: Vec<Option<&usize>> = vec![None; samples.len()];
+        measurer.measure(|| {
+            table.get_batch(&samples, &mut results);
+            results.iter().filter(|result| result.is_some()).count()
+        });
+        if let Some(index) = results.iter().position(Option::is_none) {
+            panic!("Failed to get key {}", samples[index]);
+        }
+    })
+    .unwrap();
+
+    let per_key = |result: benchmarking::MeasureResult| {
+        result.elapsed().as_nanos() as f64 / SAMPLE_SIZE as f64
+    };
+    println!(
+        "workload {}, {} keys, huge pages {}",
+        workload, SAMPLE_SIZE, huge_pages
+    );
+    println!("{:<12} {:>10} {:>10}", "", "put ns", "get ns");
+    println!(
+        "{:<12} {:>10.2} {:>10.2}",
+        "single",
+        per_key(put),
+        per_key(get)
+    );
+    println!(
+        "{:<12} {:>10.2} {:>10.2}",
+        "batch",
+        per_key(put_batch),
+        per_key(get_batch)
+    );
+}
+
+fn analyze_with_probe<H>(
+    keys: &[usize],
+    hasher: &H,
+    capacity: usize,
+    probe: &str,
+) -> analysis::HashReport
+where
+    H: KeyHasher,
+{
+    match probe {
+        "triangular" => analysis::analyze_with_probe::<H, Triangular>(keys, hasher, capacity),
+        "linear" => analysis::analyze_with_probe::<H, Linear>(keys, hasher, capacity),
+        "double" => analysis::analyze_with_probe::<H, DoubleHashing>(keys, hasher, capacity),
+        "random-walk" => analysis::analyze_with_probe::<H, RandomWalk>(keys, hasher, capacity),
+        _ => panic!("Unknown probe strategy {}", probe),
+    }
+}
+
+/// `analyze <keys file | -> [hasher | all] [seed] [capacity] [probe]`
+///
+/// Reads whitespace separated keys from the file, or from stdin for `-`, and prints a hash
+/// quality report for each selected hasher. A seed of 0 keeps the fixed Fibonacci
+/// multiplier; the capacity defaults to the number of keys. The probe strategy is one of
+/// `triangular` (the default), `linear`, `double` or `random-walk`.
+fn analyze(args: &[String]) {
+    const USAGE: &str = "usage: analyze <keys file | -> [hasher | all] [seed] [capacity] [probe]";
+    let path: &str = args.first().expect(USAGE);
+    let mut text: String = String::new();
+    if path == "-" {
+        std::io::stdin()
+            .read_to_string(&mut text)
+            .expect("Failed to read keys from stdin");
+    } else {
+        text = std::fs::read_to_string(path).expect("Failed to read the keys file");
+    }
+    let keys: Vec<usize> = text
+        .split_whitespace()
+        .map(|word| {
+            word.parse()
+                .unwrap_or_else(|_| panic!("Invalid key {}", word))
+        })
+        .collect();
+    let selected: &str = args.get(1).map_or("all", String::as_str);
+    let seed: u64 = args.get(2).map_or(0, |arg| arg.parse().expect(USAGE));
+    let capacity: usize = args
+        .get(3)
+        .map_or(keys.len(), |arg| arg.parse().expect(USAGE));
+    let probe: &str = args.get(4).map_or("triangular", String::as_str);
+
+    let report = |name: &str, hasher: &dyn Fn() -> analysis::HashReport| {
+        if selected == "all" || selected == name {
+            println!("{}\n{}\n", name, hasher());
+        }
+    };
+    let fibonacci: Fibonacci = if seed == 0 {
+        Fibonacci::default()
+    } else {
+        Fibonacci::with_seed(HashSeed::from_u64(seed))
+    };
+    report("fibonacci", &|| {
+        analyze_with_probe(&keys, &fibonacci, capacity, probe)
+    });
+    report("splitmix64", &|| {
+        analyze_with_probe(&keys, &SplitMix64::with_seed(seed), capacity, probe)
+    });
+    report("tabulation", &|| {
+        analyze_with_probe(&keys, &Tabulation::from_u64(seed), capacity, probe)
+    });
+    report("identity", &|| {
+        analyze_with_probe(&keys, &Identity, capacity, probe)
+    });
+}
+
+/// Runs the mode named by the first argument. `--huge-pages`, anywhere, maps the bucket
+/// arrays of the default benchmarks and of `batch` with huge pages.
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let huge_pages: bool = args.iter().any(|arg| arg == "--huge-pages");
+    args.retain(|arg| arg != "--huge-pages");
+    match args.first().map(String::as_str) {
+        Some("analyze") => analyze(&args[1..]),
+        Some("conformance") => conformance(&args[1..]),
+        Some("tables") => compare_tables(args.get(1).map_or("random", String::as_str)),
+        Some("latency") => latency(args.get(1).map_or("random", String::as_str)),
+        Some("grow") => compare_grow(),
+        Some("batch") => compare_batch(args.get(1).map_or("random", String::as_str), huge_pages),
+        workload => run_benchmarks(workload.unwrap_or("random"), huge_pages),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Enough operations to grow a table from 16 buckets a dozen times.
+    const OPERATIONS: usize = 20_000;
+
+    macro_rules! conformance_tests {
+        ($($name:ident: $table:ty, $operations:expr;)*) => {
+            $(
+                #[test]
+                fn $name() {
+                    for seed in 0..3 {
+                        check::<$table>(stringify!($name), $operations, seed);
+                    }
+                }
+            )*
+        };
+    }
 
+    conformance_tests! {
+        hash3: HashTable<usize>, OPERATIONS;
+        linear: HashTable<usize, usize, Fibonacci, NoHash, Linear>, OPERATIONS;
+        double: HashTable<usize, usize, Fibonacci, NoHash, DoubleHashing>, OPERATIONS;
+        random_walk: HashTable<usize, usize, Fibonacci, NoHash, RandomWalk>, OPERATIONS;
+        cached: CachedHashTable<usize>, OPERATIONS;
+        fixed_seed: FixedSeedHashTable, OPERATIONS;
+        incremental: IncrementalHashTable, OPERATIONS;
+        // Grows only run in parallel from 2 * MIN_PARTITION buckets.
+        parallel: ParallelGrowHashTable, 100_000;
+        // Only arrays of at least one huge page are mapped.
+        huge_pages: HugePageHashTable, 400_000;
+        robin_hood: RobinHoodTable<usize>, OPERATIONS;
+        cellar: CellarHashTable<usize>, OPERATIONS;
+        swiss: SwissTable<usize>, OPERATIONS;
+        cuckoo: CuckooTable<usize>, OPERATIONS;
+        hopscotch: HopscotchTable<usize>, OPERATIONS;
+        chained: ChainedTable<usize>, OPERATIONS;
+    }
 }
@@ -0,0 +1,222 @@
+use crate::hash3::hash::{Fibonacci, HashSeed, Key, KeyHasher, TableKey, USIZE_BITS};
+use std::fmt::Debug;
+use std::mem;
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Slot<K, T>
+where
+    K: TableKey,
+    T: Default + Copy + Clone + Debug,
+{
+    /// One more than the distance from the home bucket, or zero for an empty slot.
+    dist: usize,
+    key: K,
+    value: T,
+}
+
+/// Open addressing with linear probing and Robin Hood displacement, for comparison with
+/// the chained `HashTable`.
+///
+/// An insert that meets a key closer to its home bucket than the key being inserted
+/// swaps the two and carries on with the displaced key, which keeps probe lengths even.
+/// A lookup stops as soon as it meets a key closer to home than the one searched for,
+/// and `remove` shifts the following keys back one slot instead of leaving a tombstone.
+#[derive(Clone, Debug)]
+pub struct RobinHoodTable<T, K = usize, H = Fibonacci>
+where
+    T: Default + Copy + Clone + Debug,
+    K: TableKey,
+    H: KeyHasher<K>,
+{
+    count: usize,
+    shift: usize,
+    mask: usize,
+    hasher: H,
+    slots: Vec<Slot<K, T>>,
+}
+
+impl<T, K> RobinHoodTable<T, K, Fibonacci>
+where
+    T: Default + Copy + Clone + Debug,
+    K: Key,
+{
+    pub fn with_capacity(initial_capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(initial_capacity, Fibonacci::default())
+    }
+
+    pub fn with_capacity_and_seed(initial_capacity: usize, seed: HashSeed) -> Self {
+        Self::with_capacity_and_hasher(initial_capacity, Fibonacci::with_seed(seed))
+    }
+}
+
+impl<T, K, H> RobinHoodTable<T, K, H>
+where
+    T: Default + Copy + Clone + Debug,
+    K: TableKey,
+    H: KeyHasher<K>,
+{
+    pub const BUCKET_SIZE: usize = mem::size_of::<Slot<K, T>>();
+    pub const MIN_BITS: usize = 1;
+
+    pub fn with_capacity_and_hasher(initial_capacity: usize, hasher: H) -> Self {
+        let bits: usize =
+            (USIZE_BITS - initial_capacity.leading_zeros() as usize).max(Self::MIN_BITS);
+        Self::allocate(bits, hasher)
+    }
+
+    fn allocate(bits: usize, hasher: H) -> Self {
+        assert!(bits < USIZE_BITS, "Allocation too large");
+        let capacity: usize = 1 << bits;
+        RobinHoodTable {
+            count: 0,
+            shift: USIZE_BITS - bits,
+            mask: capacity - 1,
+            hasher,
+            slots: vec![Slot::default(); capacity],
+        }
+    }
+
+    #[inline]
+    fn hash(&self, key: K) -> usize {
+        self.hasher.hash(key) >> self.shift
+    }
+
+    /// Returns true if the load factor is greater than or equal to 0.875. Linear probing
+    /// degrades faster than chaining as the table fills, so it grows earlier than
+    /// `HashTable`.
+    #[inline]
+    fn should_grow(&self) -> bool {
+        self.count >= (self.slots.len() - (self.slots.len() >> 3))
+    }
+
+    #[inline]
+    pub fn load_factor(&self) -> f64 {
+        self.count as f64 / self.slots.len() as f64
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Returns the number of bytes allocated for slots.
+    #[inline]
+    pub fn allocated_bytes(&self) -> usize {
+        self.slots.len() * Self::BUCKET_SIZE
+    }
+
+    #[inline]
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Returns the slot holding `key`.
+    #[inline]
+    fn find(&self, key: K) -> Option<usize> {
+        let mut index: usize = self.hash(key);
+        let mut dist: usize = 1;
+        loop {
+            let slot: &Slot<K, T> = &self.slots[index];
+            if slot.dist < dist {
+                return None;
+            }
+            if slot.dist == dist && slot.key == key {
+                return Some(index);
+            }
+            index = (index + 1) & self.mask;
+            dist += 1;
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, key: K) -> Option<&T> {
+        self.find(key).map(|index| &self.slots[index].value)
+    }
+
+    pub fn put(&mut self, key: K, value: T) -> Option<T> {
+        if self.should_grow() {
+            self.grow();
+        }
+        let mut index: usize = self.hash(key);
+        let mut carried: Slot<K, T> = Slot {
+            dist: 1,
+            key,
+            value,
+        };
+        // Until the first swap the key may still be further along its probe sequence.
+        let mut searching: bool = true;
+        loop {
+            let slot: &mut Slot<K, T> = &mut self.slots[index];
+            if slot.dist == 0 {
+                *slot = carried;
+                self.count += 1;
+                return None;
+            }
+            if searching && slot.dist == carried.dist && slot.key == carried.key {
+                return Some(mem::replace(&mut slot.value, carried.value));
+            }
+            if slot.dist < carried.dist {
+                mem::swap(slot, &mut carried);
+                searching = false;
+            }
+            index = (index + 1) & self.mask;
+            carried.dist += 1;
+        }
+    }
+
+    /// Removes `key` and shifts the keys after it back one slot, until a key that is at
+    /// its home bucket or an empty slot.
+    pub fn remove(&mut self, key: K) -> Option<T> {
+        let mut index: usize = self.find(key)?;
+        let value: T = self.slots[index].value;
+        let mut next: usize = (index + 1) & self.mask;
+        while self.slots[next].dist > 1 {
+            self.slots[index] = self.slots[next];
+            self.slots[index].dist -= 1;
+            index = next;
+            next = (next + 1) & self.mask;
+        }
+        self.slots[index].dist = 0;
+        self.count -= 1;
+        Some(value)
+    }
+
+    fn grow(&mut self) {
+        let bits: usize = USIZE_BITS - self.shift + 1;
+        let old: Self = mem::replace(self, Self::allocate(bits, self.hasher.clone()));
+        for slot in old.slots.iter() {
+            if slot.dist != 0 {
+                self.put(slot.key, slot.value);
+            }
+        }
+    }
+
+    /// Returns an iterator over the occupied slots in slot order.
+    pub fn iter(&self) -> impl Iterator<Item = (K, &T)> + '_ {
+        self.slots
+            .iter()
+            .filter(|slot| slot.dist != 0)
+            .map(|slot| (slot.key, &slot.value))
+    }
+
+    pub fn print(&self) {
+        println!(
+            "count {}, shift {}, mask {}, cap {}, load {}",
+            self.count,
+            self.shift,
+            self.mask,
+            self.slots.len(),
+            self.load_factor()
+        );
+    }
+}
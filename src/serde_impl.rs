@@ -0,0 +1,128 @@
+use serde::de::{MapAccess, Visitor};
+use serde::Deserialize;
+use std::fmt;
+use std::marker::PhantomData;
+
+/// The most entries reserved up front from a length hint. The hint comes from the input,
+/// so a corrupt or hostile length must not size the allocation; tables grow past this as
+/// entries actually arrive.
+const MAX_RESERVED: usize = 1 << 16;
+
+/// Builds a table from a serialized map of key to value. Both table layouts share this
+/// visitor; `create` is handed the length hint, capped at `MAX_RESERVED`, so the buckets
+/// can be reserved up front and `insert` is called once per entry.
+pub(crate) struct TableVisitor<H, K, V, C, I> {
+    create: C,
+    insert: I,
+    marker: PhantomData<(H, K, V)>,
+}
+
+impl<H, K, V, C, I> TableVisitor<H, K, V, C, I>
+where
+    C: FnOnce(usize) -> H,
+    I: FnMut(&mut H, K, V),
+{
+    pub(crate) fn new(create: C, insert: I) -> Self {
+        TableVisitor {
+            create,
+            insert,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'de, H, K, V, C, I> Visitor<'de> for TableVisitor<H, K, V, C, I>
+where
+    K: Deserialize<'de>,
+    V: Deserialize<'de>,
+    C: FnOnce(usize) -> H,
+    I: FnMut(&mut H, K, V),
+{
+    type Value = H;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of integer keys to values")
+    }
+
+    fn visit_map<M: MapAccess<'de>>(mut self, mut access: M) -> Result<H, M::Error> {
+        let mut table: H = (self.create)(access.size_hint().unwrap_or(0).min(MAX_RESERVED));
+        while let Some((key, value)) = access.next_entry::<K, V>()? {
+            (self.insert)(&mut table, key, value);
+        }
+        Ok(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hash2::hash::map::HashTable as Hash2Table;
+    use crate::hash3::hash::map::HashTable;
+
+    fn keys() -> impl Iterator<Item = usize> {
+        (0..1000usize).map(|i| i.wrapping_mul(0x9E3779B97F4A7C15))
+    }
+
+    fn hash3_table() -> HashTable<u32> {
+        let mut table: HashTable<u32> = HashTable::with_capacity(16);
+        for (value, key) in keys().enumerate() {
+            table.put(key, value as u32);
+        }
+        table
+    }
+
+    fn hash2_table() -> Hash2Table<u32> {
+        let mut table: Hash2Table<u32> = Hash2Table::with_capacity(16);
+        for (value, key) in keys().enumerate() {
+            table.put(key, value as u32);
+        }
+        table
+    }
+
+    fn assert_same_hash3(table: &HashTable<u32>) {
+        assert_eq!(table.len(), 1000);
+        for (value, key) in keys().enumerate() {
+            assert_eq!(table.get(key), Some(&(value as u32)));
+        }
+    }
+
+    fn assert_same_hash2(table: &Hash2Table<u32>) {
+        assert_eq!(table.len(), 1000);
+        for (value, key) in keys().enumerate() {
+            assert_eq!(table.get(key), Some(&(value as u32)));
+        }
+    }
+
+    #[test]
+    fn hash3_json_round_trip() {
+        let json: String = serde_json::to_string(&hash3_table()).unwrap();
+        assert_same_hash3(&serde_json::from_str(&json).unwrap());
+    }
+
+    #[test]
+    fn hash3_bincode_round_trip() {
+        let bytes: Vec<u8> = bincode::serialize(&hash3_table()).unwrap();
+        assert_same_hash3(&bincode::deserialize(&bytes).unwrap());
+    }
+
+    #[test]
+    fn huge_length_prefix_is_not_reserved() {
+        // A bincode map claiming 2^40 entries, followed by a single entry.
+        let mut bytes: Vec<u8> = (1u64 << 40).to_le_bytes().to_vec();
+        bytes.extend_from_slice(&7u64.to_le_bytes());
+        bytes.extend_from_slice(&9u32.to_le_bytes());
+        assert!(bincode::deserialize::<HashTable<u32>>(&bytes).is_err());
+        assert!(bincode::deserialize::<Hash2Table<u32>>(&bytes).is_err());
+    }
+
+    #[test]
+    fn hash2_json_round_trip() {
+        let json: String = serde_json::to_string(&hash2_table()).unwrap();
+        assert_same_hash2(&serde_json::from_str(&json).unwrap());
+    }
+
+    #[test]
+    fn hash2_bincode_round_trip() {
+        let bytes: Vec<u8> = bincode::serialize(&hash2_table()).unwrap();
+        assert_same_hash2(&bincode::deserialize(&bytes).unwrap());
+    }
+}
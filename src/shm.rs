@@ -0,0 +1,538 @@
+//! A `HashTable` whose header and bucket array live in a named POSIX shared memory
+//! segment, so several processes can share one copy of a large table.
+//!
+//! The segment follows the single allocation layout of `hash2`: a header immediately
+//! followed by the bucket array. Each process maps the segment at a different address,
+//! so chains are linked by bucket index instead of by pointer.
+//!
+//! # Consistency
+//!
+//! There is exactly one writer, the process that created the segment. Every `put` is
+//! published under a sequence lock: the writer makes the version odd before touching a
+//! bucket and even again once the chain is consistent. A reader retries a lookup until it
+//! observes the same even version before and after walking the chain, so each `get`
+//! reflects the table either entirely before or entirely after any single `put`. Readers
+//! never block the writer, and lookups copy the value out rather than borrowing it.
+
+use crate::archive::{ArchivedBucket, Plain, EMPTY};
+use crate::hash3::hash::HASH_MULTIPLIER;
+use std::ffi::CString;
+use std::io::{self, Error, ErrorKind};
+use std::mem;
+use std::ptr::{self, null_mut};
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+
+const MAGIC: u64 = u64::from_ne_bytes(*b"RBSHMEM1");
+
+#[repr(C)]
+struct Header {
+    magic: u64,
+    bucket_size: u64,
+    value_size: u64,
+    capacity: u64,
+    shift: u64,
+    count: AtomicU64,
+    version: AtomicU64,
+}
+
+type Bucket<T> = ArchivedBucket<T>;
+
+/// An mmap'ed view of a shared memory segment, unmapped on drop.
+struct Segment {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl Segment {
+    fn map(name: &str, len: Option<usize>, writable: bool) -> io::Result<Self> {
+        let c_name: CString =
+            CString::new(name).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        let (flags, prot) = if writable {
+            (
+                libc::O_RDWR | libc::O_CREAT | libc::O_EXCL,
+                libc::PROT_READ | libc::PROT_WRITE,
+            )
+        } else {
+            (libc::O_RDONLY, libc::PROT_READ)
+        };
+        unsafe {
+            let fd: libc::c_int = libc::shm_open(c_name.as_ptr(), flags, 0o644);
+            if fd < 0 {
+                return Err(Error::last_os_error());
+            }
+            let result: io::Result<Self> = Self::map_fd(fd, len, prot);
+            libc::close(fd);
+            if result.is_err() && writable {
+                libc::shm_unlink(c_name.as_ptr());
+            }
+            result
+        }
+    }
+
+    unsafe fn map_fd(fd: libc::c_int, len: Option<usize>, prot: libc::c_int) -> io::Result<Self> {
+        let len: usize = match len {
+            Some(len) => {
+                let size: libc::off_t = libc::off_t::try_from(len)
+                    .map_err(|_| Error::new(ErrorKind::InvalidInput, "Allocation too large"))?;
+                if libc::ftruncate(fd, size) != 0 {
+                    return Err(Error::last_os_error());
+                }
+                len
+            }
+            None => {
+                let mut stat: libc::stat = mem::zeroed();
+                if libc::fstat(fd, &mut stat) != 0 {
+                    return Err(Error::last_os_error());
+                }
+                stat.st_size as usize
+            }
+        };
+        if len < mem::size_of::<Header>() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Shared table segment is truncated",
+            ));
+        }
+        let ptr: *mut libc::c_void = libc::mmap(null_mut(), len, prot, libc::MAP_SHARED, fd, 0);
+        if ptr == libc::MAP_FAILED {
+            return Err(Error::last_os_error());
+        }
+        Ok(Segment {
+            ptr: ptr as *mut u8,
+            len,
+        })
+    }
+
+    #[inline]
+    fn header(&self) -> &Header {
+        unsafe { &*(self.ptr as *const Header) }
+    }
+}
+
+impl Drop for Segment {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.len) };
+    }
+}
+
+#[inline]
+fn body_offset<T>() -> usize {
+    let align: usize = mem::align_of::<Bucket<T>>();
+    (mem::size_of::<Header>() + align - 1) & !(align - 1)
+}
+
+/// Removes the named segment. Processes that still have it mapped keep their mapping.
+pub fn unlink(name: &str) -> io::Result<()> {
+    let c_name: CString = CString::new(name).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    if unsafe { libc::shm_unlink(c_name.as_ptr()) } != 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The writing side of a shared table. It owns the segment it created; the segment
+/// outlives the writer until it is removed with `unlink`.
+pub struct SharedHashTable<T>
+where
+    T: Plain,
+{
+    segment: Segment,
+    buckets: *mut Bucket<T>,
+    capacity: usize,
+    mask: usize,
+    shift: usize,
+}
+
+unsafe impl<T: Plain + Send> Send for SharedHashTable<T> {}
+
+impl<T> SharedHashTable<T>
+where
+    T: Plain,
+{
+    pub const BUCKET_SIZE: usize = mem::size_of::<Bucket<T>>();
+
+    /// Creates a new segment called `name` (for example `"/ids"`) sized for
+    /// `initial_capacity` keys. The capacity is fixed because readers can not follow a
+    /// remap, so size it for the final key count.
+    pub fn create(name: &str, initial_capacity: usize) -> io::Result<Self> {
+        assert!(mem::size_of::<T>() != 0, "Capacity overflow");
+        let bits: usize = (usize::BITS - initial_capacity.leading_zeros()).max(1) as usize;
+        let capacity: usize = 1usize
+            .checked_shl(bits as u32)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Allocation too large"))?;
+        let len: usize = capacity
+            .checked_mul(Self::BUCKET_SIZE)
+            .and_then(|size| size.checked_add(body_offset::<T>()))
+            .filter(|size| *size < isize::MAX as usize)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Allocation too large"))?;
+
+        let segment: Segment = Segment::map(name, Some(len), true)?;
+        let shift: usize = capacity.leading_zeros() as usize + 1;
+        unsafe {
+            (segment.ptr as *mut Header).write(Header {
+                magic: MAGIC,
+                bucket_size: Self::BUCKET_SIZE as u64,
+                value_size: mem::size_of::<T>() as u64,
+                capacity: capacity as u64,
+                shift: shift as u64,
+                count: AtomicU64::new(0),
+                version: AtomicU64::new(0),
+            });
+        }
+        // ftruncate zero fills the segment, but the empty marker is not zero.
+        let buckets: *mut Bucket<T> =
+            unsafe { segment.ptr.add(body_offset::<T>()) } as *mut Bucket<T>;
+        for i in 0..capacity {
+            unsafe { ptr::addr_of_mut!((*buckets.add(i)).next).write(EMPTY) };
+        }
+        Ok(SharedHashTable {
+            segment,
+            buckets,
+            capacity,
+            mask: capacity - 1,
+            shift,
+        })
+    }
+
+    #[inline]
+    fn hash(&self, key: usize) -> usize {
+        key.wrapping_mul(HASH_MULTIPLIER) >> self.shift
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.segment.header().count.load(Ordering::Relaxed) as usize
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    #[inline]
+    pub fn load_factor(&self) -> f64 {
+        self.len() as f64 / self.capacity as f64
+    }
+
+    #[inline]
+    pub fn get(&self, key: usize) -> Option<&T> {
+        unsafe {
+            let origin: usize = self.hash(key);
+            let mut i: usize = origin;
+            if (*self.buckets.add(origin)).next == EMPTY {
+                return None;
+            }
+            loop {
+                let bucket: *mut Bucket<T> = self.buckets.add(i);
+                if (*bucket).key == key {
+                    return Some(&(*bucket).value);
+                }
+                i = (*bucket).next;
+                if i == origin {
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Inserts or replaces `key` and publishes the change to every attached reader.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is new and the table already holds 15/16 of its capacity, since the
+    /// segment can not grow underneath the readers. Replacing a key always succeeds.
+    pub fn put(&mut self, key: usize, value: T) -> Option<T> {
+        let header: &Header = self.segment.header();
+        let count: usize = header.count.load(Ordering::Relaxed) as usize;
+        assert!(
+            count < self.capacity - (self.capacity >> 4) || self.get(key).is_some(),
+            "Shared table is full"
+        );
+        let version: u64 = header.version.load(Ordering::Relaxed);
+        header.version.store(version + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        let replaced: Option<T> = unsafe { self.emplace(key, value) };
+
+        let header: &Header = self.segment.header();
+        if replaced.is_none() {
+            header.count.store(count as u64 + 1, Ordering::Relaxed);
+        }
+        header.version.store(version + 2, Ordering::Release);
+        replaced
+    }
+
+    #[inline]
+    unsafe fn probe(&self, mut h: usize) -> usize {
+        for x in 1usize.. {
+            h = (h + x) & self.mask;
+            if (*self.buckets.add(h)).next == EMPTY {
+                break;
+            }
+        }
+        h
+    }
+
+    unsafe fn emplace(&mut self, key: usize, value: T) -> Option<T> {
+        let b: *mut Bucket<T> = self.buckets;
+        let origin: usize = self.hash(key);
+        let next: usize = (*b.add(origin)).next;
+
+        if next == EMPTY {
+            *b.add(origin) = Bucket {
+                next: origin,
+                key,
+                value,
+            };
+            None
+        } else if self.hash((*b.add(origin)).key) == origin {
+            let mut curr: usize = origin;
+            loop {
+                if (*b.add(curr)).key == key {
+                    return Some(ptr::replace(&mut (*b.add(curr)).value, value));
+                }
+                if (*b.add(curr)).next == origin {
+                    let free: usize = self.probe(origin);
+                    *b.add(free) = Bucket {
+                        next: origin,
+                        key,
+                        value,
+                    };
+                    (*b.add(curr)).next = free;
+                    return None;
+                }
+                curr = (*b.add(curr)).next;
+            }
+        } else {
+            let mut curr: usize = next;
+            while (*b.add(curr)).next != origin {
+                curr = (*b.add(curr)).next;
+            }
+            let free: usize = self.probe(origin);
+            *b.add(free) = *b.add(origin);
+            (*b.add(curr)).next = free;
+            *b.add(origin) = Bucket {
+                next: origin,
+                key,
+                value,
+            };
+            None
+        }
+    }
+}
+
+/// A read-only attachment to a segment created by `SharedHashTable::create`.
+pub struct SharedHashTableReader<T>
+where
+    T: Plain,
+{
+    segment: Segment,
+    buckets: *const Bucket<T>,
+    capacity: usize,
+    shift: usize,
+}
+
+unsafe impl<T: Plain + Sync> Send for SharedHashTableReader<T> {}
+unsafe impl<T: Plain + Sync> Sync for SharedHashTableReader<T> {}
+
+impl<T> SharedHashTableReader<T>
+where
+    T: Plain,
+{
+    /// Maps the segment called `name` read-only after checking that its header matches
+    /// `T` and that the bucket array fits inside the segment.
+    pub fn open(name: &str) -> io::Result<Self> {
+        let segment: Segment = Segment::map(name, None, false)?;
+        let header: &Header = segment.header();
+        let invalid = |msg: &str| Err(Error::new(ErrorKind::InvalidData, msg.to_string()));
+        if header.magic != MAGIC {
+            return invalid("Segment does not contain a shared table");
+        }
+        if header.bucket_size != mem::size_of::<Bucket<T>>() as u64
+            || header.value_size != mem::size_of::<T>() as u64
+        {
+            return invalid("Shared table was created for a different value type");
+        }
+        let capacity: usize = header.capacity as usize;
+        if !capacity.is_power_of_two() || header.shift != capacity.leading_zeros() as u64 + 1 {
+            return invalid("Shared table header is corrupt");
+        }
+        let fits: bool = capacity
+            .checked_mul(mem::size_of::<Bucket<T>>())
+            .and_then(|size| size.checked_add(body_offset::<T>()))
+            .is_some_and(|size| size <= segment.len);
+        if !fits {
+            return invalid("Shared table segment is truncated");
+        }
+        let shift: usize = header.shift as usize;
+        let buckets: *const Bucket<T> =
+            unsafe { segment.ptr.add(body_offset::<T>()) } as *const Bucket<T>;
+        Ok(SharedHashTableReader {
+            segment,
+            buckets,
+            capacity,
+            shift,
+        })
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.segment.header().count.load(Ordering::Acquire) as usize
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns a copy of the value stored for `key`, retrying while the writer is in the
+    /// middle of a `put`.
+    pub fn get(&self, key: usize) -> Option<T> {
+        let version: &AtomicU64 = &self.segment.header().version;
+        loop {
+            let before: u64 = version.load(Ordering::Acquire);
+            if before & 1 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+            let found: Option<T> = unsafe { self.lookup(key) };
+            fence(Ordering::Acquire);
+            if version.load(Ordering::Relaxed) == before {
+                return found;
+            }
+        }
+    }
+
+    /// Walks the chain for `key` with volatile reads. A concurrent `put` can leave the
+    /// links inconsistent, so indices are bounds checked and the walk gives up after
+    /// `capacity` hops; `get` then discards the result when the version has moved.
+    unsafe fn lookup(&self, key: usize) -> Option<T> {
+        let origin: usize = key.wrapping_mul(HASH_MULTIPLIER) >> self.shift;
+        let mut i: usize = origin;
+        for _ in 0..self.capacity {
+            let bucket: Bucket<T> = ptr::read_volatile(self.buckets.add(i));
+            if bucket.next == EMPTY || bucket.next >= self.capacity {
+                return None;
+            }
+            if bucket.key == key {
+                return Some(bucket.value);
+            }
+            i = bucket.next;
+            if i == origin {
+                return None;
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{unlink, SharedHashTable, SharedHashTableReader};
+    use std::thread;
+
+    /// Returns a segment name no other test run uses at the same time.
+    fn segment_name(test: &str) -> String {
+        format!("/rusty_buckets_{}_{}", test, std::process::id())
+    }
+
+    #[test]
+    fn reader_sees_writer_puts() {
+        let name: String = segment_name("pair");
+        let mut writer: SharedHashTable<u64> = SharedHashTable::create(&name, 1000).unwrap();
+        let reader: SharedHashTableReader<u64> = SharedHashTableReader::open(&name).unwrap();
+        unlink(&name).unwrap();
+        assert_eq!(reader.capacity(), writer.capacity());
+        for key in 0..900usize {
+            assert_eq!(writer.put(key * 31, key as u64), None);
+        }
+        assert_eq!(writer.put(0, 7), Some(0));
+        assert_eq!(reader.len(), 900);
+        assert_eq!(reader.get(0), Some(7));
+        for key in 1..900usize {
+            assert_eq!(reader.get(key * 31), Some(key as u64));
+        }
+        assert_eq!(reader.get(1), None);
+    }
+
+    #[test]
+    fn full_table_replaces_keys() {
+        let name: String = segment_name("full");
+        let mut writer: SharedHashTable<u64> = SharedHashTable::create(&name, 16).unwrap();
+        unlink(&name).unwrap();
+        let full: usize = writer.capacity() - (writer.capacity() >> 4);
+        for key in 0..full {
+            assert_eq!(writer.put(key, key as u64), None);
+        }
+        assert_eq!(writer.put(3, 7), Some(3));
+        assert_eq!(writer.len(), full);
+        assert_eq!(writer.get(3), Some(&7));
+    }
+
+    #[test]
+    #[should_panic(expected = "Shared table is full")]
+    fn full_table_rejects_new_keys() {
+        let name: String = segment_name("overfull");
+        let mut writer: SharedHashTable<u64> = SharedHashTable::create(&name, 16).unwrap();
+        unlink(&name).unwrap();
+        let full: usize = writer.capacity() - (writer.capacity() >> 4);
+        for key in 0..=full {
+            writer.put(key, key as u64);
+        }
+    }
+
+    #[test]
+    fn reader_rejects_other_value_types() {
+        let name: String = segment_name("types");
+        let _writer: SharedHashTable<u64> = SharedHashTable::create(&name, 16).unwrap();
+        let opened = SharedHashTableReader::<u32>::open(&name);
+        unlink(&name).unwrap();
+        assert!(opened.is_err());
+    }
+
+    #[test]
+    fn create_fails_for_an_existing_name() {
+        let name: String = segment_name("exists");
+        let _writer: SharedHashTable<u64> = SharedHashTable::create(&name, 16).unwrap();
+        let again = SharedHashTable::<u64>::create(&name, 16);
+        unlink(&name).unwrap();
+        assert!(again.is_err());
+    }
+
+    #[test]
+    fn concurrent_reads_see_whole_puts() {
+        let name: String = segment_name("concurrent");
+        let mut writer: SharedHashTable<[u64; 2]> =
+            SharedHashTable::create(&name, 1 << 14).unwrap();
+        let reader: SharedHashTableReader<[u64; 2]> = SharedHashTableReader::open(&name).unwrap();
+        unlink(&name).unwrap();
+        let writes = thread::spawn(move || {
+            for round in 0..4u64 {
+                for key in 0..10_000u64 {
+                    writer.put(key as usize, [key + round, key + round]);
+                }
+            }
+        });
+        while !writes.is_finished() {
+            for key in 0..10_000u64 {
+                if let Some([first, second]) = reader.get(key as usize) {
+                    assert_eq!(first, second, "Torn value for key {}", key);
+                    assert!(first >= key && first < key + 4);
+                }
+            }
+        }
+        writes.join().unwrap();
+        assert_eq!(reader.len(), 10_000);
+        assert_eq!(reader.get(9_999), Some([10_002, 10_002]));
+    }
+}
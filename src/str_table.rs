@@ -0,0 +1,329 @@
+use crate::hash3::hash::map::CachedHashTable;
+use crate::hash3::hash::{mix64, next_seed, KeyHasher};
+use std::fmt::{self, Debug};
+
+/// Keys up to this many bytes are stored inside the bucket.
+const INLINE_CAPACITY: usize = 16;
+
+/// The size of an arena chunk. Keys longer than a chunk get a chunk of their own.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Clone, Copy)]
+union StrBytes {
+    inline: [u8; INLINE_CAPACITY],
+    ptr: *const u8,
+}
+
+/// A string key as stored in a bucket: short keys inline, longer keys as a pointer into the
+/// table's arena. A key built by `StrKey::borrowed` points at the caller's string instead
+/// and is only used for the duration of one lookup.
+#[derive(Clone, Copy)]
+struct StrKey {
+    len: usize,
+    bytes: StrBytes,
+}
+
+impl StrKey {
+    /// Builds a key that refers to `key` without copying it. The result must not outlive
+    /// `key` and must never be stored in a bucket unless it is inline.
+    #[inline]
+    fn borrowed(key: &str) -> Self {
+        let len: usize = key.len();
+        if len <= INLINE_CAPACITY {
+            let mut inline: [u8; INLINE_CAPACITY] = [0; INLINE_CAPACITY];
+            inline[..len].copy_from_slice(key.as_bytes());
+            StrKey {
+                len,
+                bytes: StrBytes { inline },
+            }
+        } else {
+            StrKey {
+                len,
+                bytes: StrBytes { ptr: key.as_ptr() },
+            }
+        }
+    }
+
+    #[inline]
+    fn is_inline(&self) -> bool {
+        self.len <= INLINE_CAPACITY
+    }
+
+    #[inline]
+    fn as_str(&self) -> &str {
+        unsafe {
+            let bytes: &[u8] = if self.is_inline() {
+                &self.bytes.inline[..self.len]
+            } else {
+                std::slice::from_raw_parts(self.bytes.ptr, self.len)
+            };
+            std::str::from_utf8_unchecked(bytes)
+        }
+    }
+}
+
+impl Default for StrKey {
+    fn default() -> Self {
+        StrKey {
+            len: 0,
+            bytes: StrBytes {
+                inline: [0; INLINE_CAPACITY],
+            },
+        }
+    }
+}
+
+impl PartialEq for StrKey {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for StrKey {}
+
+impl Debug for StrKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// Hashes the bytes of a key a word at a time and finishes with the splitmix64 mixer, so
+/// the entropy reaches the high bits the table uses.
+#[derive(Clone, Copy, Debug, Default)]
+struct StrHasher {
+    seed: u64,
+}
+
+impl KeyHasher<StrKey> for StrHasher {
+    #[inline]
+    fn hash(&self, key: StrKey) -> usize {
+        let bytes: &[u8] = key.as_str().as_bytes();
+        let mut h: u64 = self.seed ^ (bytes.len() as u64).wrapping_mul(0x9E3779B97F4A7C15);
+        let mut words = bytes.chunks_exact(8);
+        for word in &mut words {
+            let word: u64 = u64::from_le_bytes(word.try_into().unwrap());
+            h = (h.rotate_left(5) ^ word).wrapping_mul(0x9E3779B97F4A7C15);
+        }
+        let rest: &[u8] = words.remainder();
+        if !rest.is_empty() {
+            let mut word: [u8; 8] = [0; 8];
+            word[..rest.len()].copy_from_slice(rest);
+            h = (h.rotate_left(5) ^ u64::from_le_bytes(word)).wrapping_mul(0x9E3779B97F4A7C15);
+        }
+        mix64(h) as usize
+    }
+
+    fn reseed(&mut self) -> bool {
+        self.seed = next_seed(self.seed);
+        true
+    }
+}
+
+/// Append-only storage for long keys. Chunks are never reallocated, so a key keeps its
+/// address for as long as the table lives.
+#[derive(Default)]
+struct Arena {
+    chunks: Vec<Box<[u8]>>,
+    used: usize,
+}
+
+impl Arena {
+    fn alloc(&mut self, key: &str) -> *const u8 {
+        let len: usize = key.len();
+        let fits: bool = self
+            .chunks
+            .last()
+            .is_some_and(|chunk| chunk.len() - self.used >= len);
+        if !fits {
+            self.chunks
+                .push(vec![0; len.max(CHUNK_SIZE)].into_boxed_slice());
+            self.used = 0;
+        }
+        let chunk: &mut [u8] = self.chunks.last_mut().unwrap();
+        let dest: &mut [u8] = &mut chunk[self.used..self.used + len];
+        dest.copy_from_slice(key.as_bytes());
+        self.used += len;
+        dest.as_ptr()
+    }
+
+    fn bytes(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.len()).sum()
+    }
+}
+
+/// A table keyed by strings.
+///
+/// Keys of up to 16 bytes are stored inline in their bucket; longer keys are copied once
+/// into an arena of large chunks owned by the table. Buckets cache the full hash of their
+/// key, so growing never rehashes strings and most mismatches are rejected without
+/// comparing bytes. Lookups take `&str` and do not allocate.
+pub struct StrTable<T>
+where
+    T: Default + Copy + Clone + Debug,
+{
+    table: CachedHashTable<T, StrKey, StrHasher>,
+    arena: Arena,
+}
+
+impl<T> StrTable<T>
+where
+    T: Default + Copy + Clone + Debug,
+{
+    pub fn with_capacity(initial_capacity: usize) -> Self {
+        Self::with_capacity_and_seed(initial_capacity, 0)
+    }
+
+    /// Creates a table whose string hasher is keyed by `seed`. Use a random seed for tables
+    /// keyed by untrusted input.
+    pub fn with_capacity_and_seed(initial_capacity: usize, seed: u64) -> Self {
+        StrTable {
+            table: CachedHashTable::with_capacity_and_hasher(initial_capacity, StrHasher { seed }),
+            arena: Arena::default(),
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    #[inline]
+    pub fn load_factor(&self) -> f64 {
+        self.table.load_factor()
+    }
+
+    /// Returns the number of bytes reserved for keys longer than the inline capacity.
+    pub fn arena_bytes(&self) -> usize {
+        self.arena.bytes()
+    }
+
+    #[inline]
+    pub fn get(&self, key: &str) -> Option<&T> {
+        self.table.get(StrKey::borrowed(key))
+    }
+
+    /// Inserts `key` and returns the previous value if it was already present. A long key
+    /// is copied into the arena only when it is new.
+    #[inline]
+    pub fn put(&mut self, key: &str, value: T) -> Option<T> {
+        let borrowed: StrKey = StrKey::borrowed(key);
+        let hash: usize = self.table.hash_key(borrowed);
+        if borrowed.is_inline() || self.table.get_with_hash(hash, borrowed).is_some() {
+            // A present key only has its value replaced, so the borrowed key is never
+            // stored.
+            return self.table.insert_with_fresh_hash(hash, borrowed, value);
+        }
+        let owned: StrKey = StrKey {
+            len: key.len(),
+            bytes: StrBytes {
+                ptr: self.arena.alloc(key),
+            },
+        };
+        self.table.insert_with_fresh_hash(hash, owned, value)
+    }
+
+    /// Returns an iterator over the keys and values in bucket order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &T)> + '_ {
+        self.table
+            .occupied()
+            .map(|(_, bucket)| (bucket.key.as_str(), &bucket.value))
+    }
+}
+
+impl<T> Debug for StrTable<T>
+where
+    T: Default + Copy + Clone + Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StrTable, CHUNK_SIZE, INLINE_CAPACITY};
+
+    fn long_key(i: usize) -> String {
+        format!("a key long enough for the arena {:08}", i)
+    }
+
+    #[test]
+    fn inline_and_arena_keys_round_trip() {
+        let mut table: StrTable<usize> = StrTable::with_capacity(16);
+        let short: &str = "short";
+        let long: String = long_key(0);
+        assert_eq!(table.put("", 1), None);
+        assert_eq!(table.put(short, 2), None);
+        assert_eq!(table.put(&long, 3), None);
+        assert_eq!(table.get(""), Some(&1));
+        assert_eq!(table.get(short), Some(&2));
+        assert_eq!(table.get(&long), Some(&3));
+        assert_eq!(table.arena_bytes(), CHUNK_SIZE);
+    }
+
+    #[test]
+    fn keys_at_the_inline_boundary() {
+        let mut table: StrTable<usize> = StrTable::with_capacity(16);
+        let inline: String = "x".repeat(INLINE_CAPACITY);
+        let arena: String = "x".repeat(INLINE_CAPACITY + 1);
+        table.put(&inline, 1);
+        assert_eq!(table.arena_bytes(), 0);
+        table.put(&arena, 2);
+        assert_eq!(table.arena_bytes(), CHUNK_SIZE);
+        assert_eq!(table.get(&inline), Some(&1));
+        assert_eq!(table.get(&arena), Some(&2));
+        assert_eq!(table.get(&"x".repeat(INLINE_CAPACITY - 1)), None);
+    }
+
+    #[test]
+    fn replacing_a_key_does_not_copy_it_again() {
+        let mut table: StrTable<usize> = StrTable::with_capacity(16);
+        let long: String = "y".repeat(CHUNK_SIZE);
+        assert_eq!(table.put(&long, 1), None);
+        assert_eq!(table.put(&long, 2), Some(1));
+        assert_eq!(table.put("short", 3), None);
+        assert_eq!(table.put("short", 4), Some(3));
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.arena_bytes(), CHUNK_SIZE);
+        assert_eq!(table.get(&long), Some(&2));
+        assert_eq!(table.get("short"), Some(&4));
+    }
+
+    #[test]
+    fn misses() {
+        let mut table: StrTable<usize> = StrTable::with_capacity(16);
+        let long: String = long_key(1);
+        table.put(&long, 1);
+        table.put("short", 2);
+        assert_eq!(table.get(&long_key(2)), None);
+        assert_eq!(table.get(&long[..long.len() - 1]), None);
+        assert_eq!(table.get("shorT"), None);
+        assert_eq!(table.get(""), None);
+    }
+
+    #[test]
+    fn arena_keys_survive_growth() {
+        let mut table: StrTable<usize> = StrTable::with_capacity(16);
+        let count: usize = 10_000;
+        for i in 0..count {
+            assert_eq!(table.put(&long_key(i), i), None);
+            assert_eq!(table.put(&i.to_string(), i), None);
+        }
+        assert_eq!(table.len(), 2 * count);
+        assert!(table.arena_bytes() > CHUNK_SIZE);
+        for i in 0..count {
+            assert_eq!(table.get(&long_key(i)), Some(&i));
+            assert_eq!(table.get(&i.to_string()), Some(&i));
+        }
+        assert_eq!(table.iter().count(), 2 * count);
+        assert!(table
+            .iter()
+            .all(|(key, value)| { *key == long_key(*value) || *key == value.to_string() }));
+    }
+}
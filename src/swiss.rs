@@ -0,0 +1,290 @@
+use crate::hash3::hash::{Fibonacci, HashSeed, Key, KeyHasher, TableKey, USIZE_BITS};
+use std::fmt::Debug;
+use std::mem;
+
+/// The number of control bytes matched at once.
+const GROUP_WIDTH: usize = 16;
+
+/// The control byte of an empty slot. Full slots hold 7 bits of hash, so their top bit is
+/// clear.
+const EMPTY: u8 = 0x80;
+
+/// Matching of control bytes 16 at a time. Bit `i` of a returned mask is set when byte
+/// `i` of the group matches.
+#[cfg(all(target_arch = "x86_64", target_feature = "sse2"))]
+mod group {
+    use super::{EMPTY, GROUP_WIDTH};
+    use std::arch::x86_64::{
+        __m128i, _mm_cmpeq_epi8, _mm_loadu_si128, _mm_movemask_epi8, _mm_set1_epi8,
+    };
+
+    #[derive(Clone, Copy)]
+    pub(super) struct Group(__m128i);
+
+    impl Group {
+        #[inline]
+        pub(super) fn load(ctrl: &[u8]) -> Self {
+            assert!(ctrl.len() >= GROUP_WIDTH);
+            unsafe { Group(_mm_loadu_si128(ctrl.as_ptr() as *const __m128i)) }
+        }
+
+        #[inline]
+        pub(super) fn match_byte(self, byte: u8) -> u16 {
+            unsafe { _mm_movemask_epi8(_mm_cmpeq_epi8(self.0, _mm_set1_epi8(byte as i8))) as u16 }
+        }
+
+        #[inline]
+        pub(super) fn match_empty(self) -> u16 {
+            self.match_byte(EMPTY)
+        }
+    }
+}
+
+#[cfg(not(all(target_arch = "x86_64", target_feature = "sse2")))]
+mod group {
+    use super::{EMPTY, GROUP_WIDTH};
+
+    #[derive(Clone, Copy)]
+    pub(super) struct Group([u8; GROUP_WIDTH]);
+
+    impl Group {
+        #[inline]
+        pub(super) fn load(ctrl: &[u8]) -> Self {
+            Group(ctrl[..GROUP_WIDTH].try_into().unwrap())
+        }
+
+        #[inline]
+        pub(super) fn match_byte(self, byte: u8) -> u16 {
+            let mut mask: u16 = 0;
+            for (i, ctrl) in self.0.iter().enumerate() {
+                mask |= ((*ctrl == byte) as u16) << i;
+            }
+            mask
+        }
+
+        #[inline]
+        pub(super) fn match_empty(self) -> u16 {
+            self.match_byte(EMPTY)
+        }
+    }
+}
+
+use group::Group;
+
+/// Iterates over the indexes of the set bits of a group mask, lowest first.
+#[inline]
+fn bits(mut mask: u16) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if mask == 0 {
+            None
+        } else {
+            let bit: usize = mask.trailing_zeros() as usize;
+            mask &= mask - 1;
+            Some(bit)
+        }
+    })
+}
+
+/// Open addressing in the style of SwissTable, for comparison with the chained
+/// `HashTable` on workloads with many misses.
+///
+/// Next to the slots the table keeps one control byte per slot, holding 7 bits of the
+/// key's hash or a marker for an empty slot. A lookup loads the control bytes of 16
+/// slots at once, compares them all with the key's 7 bits, and only reads the slots
+/// whose bytes match; one empty byte in the group ends a miss. The first 16 control
+/// bytes are mirrored after the last so a group starting near the end can be loaded in
+/// one piece. Groups are probed at triangular offsets, like `HashTable`'s buckets.
+///
+/// The home slot comes from the top bits of the hash, as in `HashTable`, and the 7 bits
+/// in the control byte are the ones just below them.
+#[derive(Clone, Debug)]
+pub struct SwissTable<T, K = usize, H = Fibonacci>
+where
+    T: Default + Copy + Clone + Debug,
+    K: TableKey,
+    H: KeyHasher<K>,
+{
+    count: usize,
+    shift: usize,
+    mask: usize,
+    hasher: H,
+    ctrl: Vec<u8>,
+    slots: Vec<(K, T)>,
+}
+
+impl<T, K> SwissTable<T, K, Fibonacci>
+where
+    T: Default + Copy + Clone + Debug,
+    K: Key,
+{
+    pub fn with_capacity(initial_capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(initial_capacity, Fibonacci::default())
+    }
+
+    pub fn with_capacity_and_seed(initial_capacity: usize, seed: HashSeed) -> Self {
+        Self::with_capacity_and_hasher(initial_capacity, Fibonacci::with_seed(seed))
+    }
+}
+
+impl<T, K, H> SwissTable<T, K, H>
+where
+    T: Default + Copy + Clone + Debug,
+    K: TableKey,
+    H: KeyHasher<K>,
+{
+    pub const BUCKET_SIZE: usize = mem::size_of::<(K, T)>() + 1;
+    /// A table is never smaller than one group, so a group load never wraps onto itself.
+    pub const MIN_BITS: usize = 4;
+
+    pub fn with_capacity_and_hasher(initial_capacity: usize, hasher: H) -> Self {
+        let bits: usize =
+            (USIZE_BITS - initial_capacity.leading_zeros() as usize).max(Self::MIN_BITS);
+        Self::allocate(bits, hasher)
+    }
+
+    fn allocate(bits: usize, hasher: H) -> Self {
+        assert!(bits + 7 <= USIZE_BITS, "Allocation too large");
+        let capacity: usize = 1 << bits;
+        SwissTable {
+            count: 0,
+            shift: USIZE_BITS - bits,
+            mask: capacity - 1,
+            hasher,
+            ctrl: vec![EMPTY; capacity + GROUP_WIDTH],
+            slots: vec![(K::default(), T::default()); capacity],
+        }
+    }
+
+    /// Returns true if the load factor is greater than or equal to 0.875.
+    #[inline]
+    fn should_grow(&self) -> bool {
+        self.count >= (self.slots.len() - (self.slots.len() >> 3))
+    }
+
+    #[inline]
+    pub fn load_factor(&self) -> f64 {
+        self.count as f64 / self.slots.len() as f64
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Returns the number of bytes allocated for slots and control bytes.
+    #[inline]
+    pub fn allocated_bytes(&self) -> usize {
+        self.slots.len() * Self::BUCKET_SIZE + GROUP_WIDTH
+    }
+
+    #[inline]
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Splits a hash into the home slot and the 7 bits kept in the control byte.
+    #[inline]
+    fn split(&self, hash: usize) -> (usize, u8) {
+        (
+            hash >> self.shift,
+            ((hash >> (self.shift - 7)) & 0x7F) as u8,
+        )
+    }
+
+    #[inline]
+    fn set_ctrl(&mut self, index: usize, byte: u8) {
+        self.ctrl[index] = byte;
+        if index < GROUP_WIDTH {
+            self.ctrl[self.slots.len() + index] = byte;
+        }
+    }
+
+    /// Returns the slot holding `key`, or else the empty slot where it would be inserted.
+    #[inline]
+    fn find(&self, hash: usize, key: K) -> Result<usize, usize> {
+        let (mut pos, h2): (usize, u8) = self.split(hash);
+        let mut stride: usize = 0;
+        loop {
+            let group: Group = Group::load(&self.ctrl[pos..]);
+            for bit in bits(group.match_byte(h2)) {
+                let index: usize = (pos + bit) & self.mask;
+                if self.slots[index].0 == key {
+                    return Ok(index);
+                }
+            }
+            let empty: u16 = group.match_empty();
+            if empty != 0 {
+                return Err((pos + empty.trailing_zeros() as usize) & self.mask);
+            }
+            stride += GROUP_WIDTH;
+            pos = (pos + stride) & self.mask;
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, key: K) -> Option<&T> {
+        match self.find(self.hasher.hash(key), key) {
+            Ok(index) => Some(&self.slots[index].1),
+            Err(_) => None,
+        }
+    }
+
+    pub fn put(&mut self, key: K, value: T) -> Option<T> {
+        if self.should_grow() {
+            self.grow();
+        }
+        let hash: usize = self.hasher.hash(key);
+        match self.find(hash, key) {
+            Ok(index) => Some(mem::replace(&mut self.slots[index].1, value)),
+            Err(index) => {
+                self.set_ctrl(index, self.split(hash).1);
+                self.slots[index] = (key, value);
+                self.count += 1;
+                None
+            }
+        }
+    }
+
+    fn grow(&mut self) {
+        let bits: usize = USIZE_BITS - self.shift + 1;
+        let old: Self = mem::replace(self, Self::allocate(bits, self.hasher.clone()));
+        for (key, value) in old.iter() {
+            let hash: usize = self.hasher.hash(key);
+            if let Err(index) = self.find(hash, key) {
+                self.set_ctrl(index, self.split(hash).1);
+                self.slots[index] = (key, *value);
+            }
+        }
+        self.count = old.count;
+    }
+
+    /// Returns an iterator over the full slots in slot order.
+    pub fn iter(&self) -> impl Iterator<Item = (K, &T)> + '_ {
+        self.ctrl[..self.slots.len()]
+            .iter()
+            .zip(self.slots.iter())
+            .filter(|(ctrl, _)| **ctrl != EMPTY)
+            .map(|(_, (key, value))| (*key, value))
+    }
+
+    pub fn print(&self) {
+        println!(
+            "count {}, shift {}, mask {}, cap {}, load {}",
+            self.count,
+            self.shift,
+            self.mask,
+            self.slots.len(),
+            self.load_factor()
+        );
+    }
+}